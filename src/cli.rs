@@ -0,0 +1,213 @@
+use crate::config::{self, MinCores, Settings};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Cores,
+    WhatIf,
+    SelfTest,
+    Status,
+    AssertReadonly,
+    Apply,
+    Info,
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "observer",
+    version,
+    about = "Parks and wakes CPU cores to follow the load"
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub subcommand: Option<SubCommand>,
+
+    /// Load settings from PATH only, instead of the default search list
+    /// ($OBSERVER_CONFIG takes precedence when set)
+    #[arg(long = "config", value_name = "PATH", global = true)]
+    pub config_path: Option<String>,
+    /// Override check_interval_sec
+    #[arg(long = "check-interval", value_name = "SECS", global = true)]
+    pub check_interval_sec: Option<u64>,
+    /// Override min_cores
+    #[arg(long, value_name = "N|PCT%", global = true)]
+    pub min_cores: Option<MinCores>,
+    /// Log at LEVEL (error, warn, info, debug, trace) instead of RUST_LOG
+    #[arg(long, value_name = "LEVEL", global = true)]
+    pub log_level: Option<LevelFilter>,
+    /// Log the core and EPP changes observer would make without writing them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Run a single decision, apply it, leave it in place and exit
+    #[arg(long)]
+    pub once: bool,
+    /// Run for SECS seconds, then restore cores and exit
+    #[arg(long = "duration", value_name = "SECS")]
+    pub duration_sec: Option<u64>,
+
+    /// Print per-core usage and online state, then exit
+    #[arg(long, group = "mode")]
+    pub cores: bool,
+    /// Print the running service's status file, then exit
+    #[arg(long, group = "mode")]
+    pub status: bool,
+    /// Offline and re-online each core to verify hotplug works, then exit
+    #[arg(long, group = "mode")]
+    pub selftest: bool,
+    /// Run one decision and exit nonzero if it would write to sysfs
+    /// (uses --load, --battery and --charge when given, measures otherwise)
+    #[arg(long, group = "mode")]
+    pub assert_readonly: bool,
+    /// Print the core count the config picks for --load, then exit
+    #[arg(long, group = "mode", requires = "load")]
+    pub what_if: bool,
+
+    /// Average CPU load to simulate
+    #[arg(long, value_name = "PCT")]
+    pub load: Option<f32>,
+    /// Simulate running on battery (default: AC)
+    #[arg(long = "battery")]
+    pub on_battery: bool,
+    /// Battery charge to simulate on battery, for low_battery_profile
+    #[arg(long, value_name = "PCT")]
+    pub charge: Option<f32>,
+    /// Cores online before the decision (default: all)
+    #[arg(long = "current", value_name = "N")]
+    pub current_cores: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SubCommand {
+    /// Set PROFILE's core count and EPP/governor/turbo, leave them in place and exit.
+    /// PROFILE is performance, balanced, powersave or a [profiles.<name>] table from the config
+    Apply {
+        #[arg(value_name = "PROFILE")]
+        profile: String,
+    },
+    /// Print the detected topology, power state, effective settings and config files in
+    /// use, then exit. Works without root
+    Info,
+}
+
+impl Args {
+    /// What to do, from the subcommand or mode flag given; running the service otherwise.
+    pub fn command(&self) -> Command {
+        match self.subcommand {
+            Some(SubCommand::Apply { .. }) => Command::Apply,
+            Some(SubCommand::Info) => Command::Info,
+            None if self.cores => Command::Cores,
+            None if self.status => Command::Status,
+            None if self.selftest => Command::SelfTest,
+            None if self.assert_readonly => Command::AssertReadonly,
+            None if self.what_if => Command::WhatIf,
+            None => Command::Run,
+        }
+    }
+
+    /// The profile named by `apply`.
+    pub fn profile(&self) -> Option<&str> {
+        match &self.subcommand {
+            Some(SubCommand::Apply { profile }) => Some(profile),
+            _ => None,
+        }
+    }
+
+    /// Applies the flags that override config file values, which in turn override the
+    /// defaults. The overridden values are validated and clamped like the config's.
+    pub fn apply_overrides(&self, settings: &mut Settings) {
+        settings.dry_run |= self.dry_run;
+        if let Some(interval) = self.check_interval_sec {
+            settings.check_interval_sec = interval;
+        }
+        if let Some(min_cores) = self.min_cores {
+            settings.min_cores = min_cores;
+        }
+        config::clamp_with_warnings(settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_strs(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once(&"observer").chain(args))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse_strs(&["--duration", "600"]).unwrap();
+        assert_eq!(args.command(), Command::Run);
+        assert_eq!(args.duration_sec, Some(600));
+        assert!(!args.dry_run);
+        assert!(parse_strs(&["--dry-run"]).unwrap().dry_run);
+
+        let args = parse_strs(&[
+            "--config",
+            "/tmp/observer.toml",
+            "--check-interval",
+            "2",
+            "--min-cores",
+            "25%",
+            "--log-level",
+            "debug",
+            "--once",
+        ])
+        .unwrap();
+        assert_eq!(args.config_path.as_deref(), Some("/tmp/observer.toml"));
+        assert_eq!(args.log_level, Some(LevelFilter::Debug));
+        assert!(args.once);
+        let mut settings = Settings::default();
+        args.apply_overrides(&mut settings);
+        assert_eq!(settings.check_interval_sec, 2);
+        assert_eq!(settings.min_cores, MinCores::Percent(25.0));
+
+        // Overrides go through the same checks as the config file
+        let args = parse_strs(&["--check-interval", "0", "--min-cores", "0"]).unwrap();
+        let mut settings = Settings::default();
+        args.apply_overrides(&mut settings);
+        assert_eq!(settings.check_interval_sec, 1);
+        assert_eq!(settings.min_cores, MinCores::Count(1));
+        assert!(parse_strs(&["--log-level", "loud"]).is_err());
+        assert!(parse_strs(&["--min-cores", "many"]).is_err());
+
+        assert_eq!(parse_strs(&["--cores"]).unwrap().command(), Command::Cores);
+        let args = parse_strs(&["apply", "performance", "--dry-run"]).unwrap();
+        assert_eq!(args.command(), Command::Apply);
+        assert_eq!(args.profile(), Some("performance"));
+        assert!(args.dry_run);
+        assert!(parse_strs(&["apply"]).is_err());
+        assert_eq!(parse_strs(&["info"]).unwrap().command(), Command::Info);
+        assert!(parse_strs(&["--duration"]).is_err());
+        assert!(parse_strs(&["--duration", "soon"]).is_err());
+        assert!(parse_strs(&["--bogus"]).is_err());
+
+        let args = parse_strs(&["--what-if", "--load", "65", "--battery"]).unwrap();
+        assert_eq!(args.command(), Command::WhatIf);
+        assert_eq!(args.load, Some(65.0));
+        assert!(args.on_battery);
+        let args =
+            parse_strs(&["--what-if", "--load", "5", "--battery", "--charge", "12"]).unwrap();
+        assert_eq!(args.charge, Some(12.0));
+        assert!(parse_strs(&["--what-if"]).is_err());
+        assert_eq!(
+            parse_strs(&["--selftest"]).unwrap().command(),
+            Command::SelfTest
+        );
+        assert_eq!(
+            parse_strs(&["--status"]).unwrap().command(),
+            Command::Status
+        );
+        let args = parse_strs(&["--assert-readonly", "--load", "10"]).unwrap();
+        assert_eq!(args.command(), Command::AssertReadonly);
+        assert_eq!(args.load, Some(10.0));
+        assert!(parse_strs(&["--cores", "--status"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        <Args as clap::CommandFactory>::command().debug_assert();
+    }
+}
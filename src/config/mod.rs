@@ -1,41 +1,208 @@
 mod settings;
 
-use config::{Config, ConfigError, File};
+use config::{Config, ConfigError, Environment, File};
 use log::{debug, info, warn};
-pub use settings::Settings;
+pub use settings::{
+    AssumedPowerState, EppCoreOrder, InputIdleSource, LoadSmoothing, LogBackend, MinCores,
+    OfflinePriority, PercentageRounding, Profile, ScalingMode, Settings, ThrottleMethod,
+};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::SystemTime;
 
-pub fn load_config() -> Result<Settings, ConfigError> {
-    debug!("Attempting to load configuration...");
+// Searched in this order, each file overriding the ones before it. The per-user file
+// from user_config_path() goes between the two groups.
+const SYSTEM_CONFIG_PATHS: [&str; 2] = ["/etc/observer/config.toml", "/etc/observer/config"];
+const LOCAL_CONFIG_PATHS: [&str; 2] = ["config.toml", "config"];
+
+// Names one config file to use exclusively, taking precedence over --config
+const CONFIG_ENV: &str = "OBSERVER_CONFIG";
+
+// Environment overrides for any setting, e.g. OBSERVER__MIN_CORES=4. The double
+// underscore keeps them apart from OBSERVER_CONFIG and from the underscores in key names.
+const ENV_PREFIX: &str = "OBSERVER";
+const ENV_SEPARATOR: &str = "__";
+
+// Set by --config; replaces the search list
+static CONFIG_FILE: OnceLock<String> = OnceLock::new();
 
-    let config_paths = [
-        "/etc/observer/config.toml",
-        "/etc/observer/config",
-        "config.toml",
-        "config",
-    ];
+/// Loads settings from `path` only, instead of searching the default paths. The file must
+/// exist. Takes effect for every later load, including auto reloads.
+pub fn set_config_file(path: String) {
+    let _ = CONFIG_FILE.set(path);
+}
 
-    let mut builder = Config::builder();
+/// `$XDG_CONFIG_HOME/observer/config.toml`, or `~/.config/observer/config.toml` when
+/// XDG_CONFIG_HOME is unset or not absolute.
+fn user_config_path(xdg_config_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    let config_home = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| {
+            home.filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".config"))
+        })?;
+    Some(config_home.join("observer/config.toml"))
+}
 
-    for path in &config_paths {
-        debug!("Checking for config at: {}", path);
-        builder = builder.add_source(File::with_name(path).required(false));
+/// The config files to load, lowest precedence first, and whether they must exist:
+/// OBSERVER_CONFIG, then --config, each used alone, otherwise the search list.
+fn config_paths() -> (Vec<String>, bool) {
+    if let Some(path) = env::var(CONFIG_ENV).ok().filter(|path| !path.is_empty()) {
+        return (vec![path], true);
+    }
+    if let Some(path) = CONFIG_FILE.get() {
+        return (vec![path.clone()], true);
     }
+    let mut paths: Vec<String> = SYSTEM_CONFIG_PATHS.iter().map(|p| p.to_string()).collect();
+    if let Some(path) = user_config_path(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+        paths.push(path.to_string_lossy().into_owned());
+    }
+    paths.extend(LOCAL_CONFIG_PATHS.iter().map(|p| p.to_string()));
+    (paths, false)
+}
 
-    match builder.build() {
-        Ok(config) => match config.try_deserialize() {
-            Ok(settings) => {
-                info!("Successfully loaded configuration");
-                debug!("Loaded settings: {:?}", settings);
-                Ok(settings)
-            }
-            Err(e) => {
-                warn!("Failed to deserialize config, using defaults: {}", e);
-                Ok(Settings::default())
+/// The config files that exist and are read, in load order; later ones override earlier ones.
+pub fn config_files_in_use() -> Vec<String> {
+    config_paths()
+        .0
+        .into_iter()
+        .filter(|path| fs::metadata(path).is_ok())
+        .collect()
+}
+
+pub fn load_config() -> Result<Settings, ConfigError> {
+    debug!("Attempting to load configuration...");
+
+    match try_load_config() {
+        Ok(settings) => {
+            match config_files_in_use().last() {
+                Some(path) => info!("Successfully loaded configuration from {}", path),
+                None => info!("No config file found, using defaults"),
             }
-        },
+            debug!("Loaded settings: {:?}", settings);
+            Ok(settings)
+        }
         Err(e) => {
             warn!("Failed to load config, using defaults: {}", e);
             Ok(Settings::default())
         }
     }
 }
+
+/// Loads the configuration without falling back to defaults, so a reload can keep the
+/// running settings when the file is broken.
+pub fn try_load_config() -> Result<Settings, ConfigError> {
+    let (paths, required) = config_paths();
+    load_from(&paths, required)
+}
+
+/// Reads `paths` in order, then the OBSERVER__<KEY> environment overrides on top. Env
+/// values that don't deserialize are dropped with a warning so the files still apply.
+fn load_from(paths: &[String], required: bool) -> Result<Settings, ConfigError> {
+    let files = || {
+        let mut builder = Config::builder();
+        for path in paths {
+            debug!("Checking for config at: {}", path);
+            builder = builder.add_source(File::with_name(path).required(required));
+        }
+        builder
+    };
+    let environment = Environment::with_prefix(ENV_PREFIX)
+        .separator(ENV_SEPARATOR)
+        .try_parsing(true);
+
+    let mut settings: Settings = match files()
+        .add_source(environment)
+        .build()
+        .and_then(|config| config.try_deserialize())
+    {
+        Ok(settings) => settings,
+        Err(e) => {
+            // A broken file fails the same way without the overrides; only blame them if not
+            let settings = files().build()?.try_deserialize()?;
+            warn!(
+                "Ignoring {}{}* environment overrides: {}",
+                ENV_PREFIX, ENV_SEPARATOR, e
+            );
+            settings
+        }
+    };
+    clamp_with_warnings(&mut settings);
+    Ok(settings)
+}
+
+/// Logs each value `Settings::validate` rejects and clamps it, for settings that came from
+/// somewhere a user can get wrong: config files, environment or command-line overrides.
+pub fn clamp_with_warnings(settings: &mut Settings) {
+    if let Err(violations) = settings.validate() {
+        for violation in violations {
+            warn!("Invalid config value: {}", violation);
+        }
+        settings.clamp_invalid();
+    }
+}
+
+/// Latest modification time among the config files that exist, for noticing edits.
+pub fn config_modified() -> Option<SystemTime> {
+    config_paths()
+        .0
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_overrides_file() {
+        let path =
+            std::env::temp_dir().join(format!("observer-env-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "min_change_interval_sec = 7\nload_window_sec = 40\ncheck_interval_sec = 3\n",
+        )
+        .unwrap();
+        let paths = [path.to_string_lossy().into_owned()];
+
+        env::set_var("OBSERVER__MIN_CHANGE_INTERVAL_SEC", "11");
+        let settings = load_from(&paths, true).unwrap();
+        assert_eq!(settings.min_change_interval_sec, 11);
+        assert_eq!(settings.load_window_sec, 40);
+
+        // An unparsable override is dropped, keeping the file rather than the defaults
+        env::set_var("OBSERVER__CHECK_INTERVAL_SEC", "soon");
+        let settings = load_from(&paths, true).unwrap();
+        assert_eq!(settings.check_interval_sec, 3);
+        assert_eq!(settings.load_window_sec, 40);
+
+        env::remove_var("OBSERVER__MIN_CHANGE_INTERVAL_SEC");
+        env::remove_var("OBSERVER__CHECK_INTERVAL_SEC");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_user_config_path() {
+        let expected = Some(PathBuf::from("/xdg/observer/config.toml"));
+        assert_eq!(
+            user_config_path(Some("/xdg".into()), Some("/home/me".into())),
+            expected
+        );
+        // Relative or empty XDG_CONFIG_HOME is ignored per the spec
+        let fallback = Some(PathBuf::from("/home/me/.config/observer/config.toml"));
+        assert_eq!(
+            user_config_path(Some("xdg".into()), Some("/home/me".into())),
+            fallback
+        );
+        assert_eq!(
+            user_config_path(Some("".into()), Some("/home/me".into())),
+            fallback
+        );
+        assert_eq!(user_config_path(None, None), None);
+    }
+}
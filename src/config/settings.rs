@@ -1,6 +1,200 @@
-use serde::Deserialize;
+use log::warn;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize, Clone)]
+use crate::system::PowerState;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThrottleMethod {
+    Offline,    // Hotplug cores off via cpuN/online
+    IdleInject, // Keep cores online, inject idle via intel_powerclamp
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflinePriority {
+    Efficiency,  // Offline E-cores first, keeping P-cores for performance
+    Performance, // Offline P-cores first, keeping E-cores for efficiency
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InputIdleSource {
+    Logind, // Session IdleHint via loginctl
+    Tty,    // Last access time of terminal devices
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadSmoothing {
+    Mean,            // Every sample in the window counts equally
+    RecencyWeighted, // Newer samples count more, linearly fading to the window edge
+    Ewma,            // Exponential moving average over the window, weighted by load_ewma_alpha
+    Percentile,      // The load_percentile-th percentile, so short blips don't count
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogBackend {
+    Auto,     // The journal when started by systemd (JOURNAL_STREAM set), stdout otherwise
+    Stdout,   // Plain text on stdout
+    Journald, // Native journal records with PRIORITY, falling back to stdout
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScalingMode {
+    Fixed,        // Always move core_step cores
+    Proportional, // Move more cores the further load is from the threshold
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AssumedPowerState {
+    Ac,
+    Battery,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EppCoreOrder {
+    EppFirst,   // Apply EPP/governor, wait the gap, then hotplug
+    CoresFirst, // Hotplug, wait the gap, then apply EPP/governor
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PercentageRounding {
+    Ceil,  // 50% of 9 cores keeps 5
+    Floor, // 50% of 9 cores keeps 4, saving more power
+    Round, // Nearest, halves rounding up
+}
+
+/// The core floor, either an absolute count (`min_cores = 2`) or a share of all cores
+/// (`min_cores = "25%"`) so one config fits machines of any size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MinCores {
+    Count(usize),
+    Percent(f32),
+}
+
+impl MinCores {
+    /// The absolute floor for `total_cores`. Percentages round up, and the result is at
+    /// least 1 since the boot CPU always stays online.
+    pub fn resolve(&self, total_cores: usize) -> usize {
+        let cores = match *self {
+            MinCores::Count(count) => count,
+            MinCores::Percent(percent) => (total_cores as f32 * percent / 100.0).ceil() as usize,
+        };
+        cores.max(1)
+    }
+}
+
+impl fmt::Display for MinCores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinCores::Count(count) => write!(f, "{}", count),
+            MinCores::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+impl FromStr for MinCores {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        match value.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f32>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(MinCores::Percent(percent)),
+                _ => Err(format!(
+                    "min_cores '{}' must be a percentage within (0, 100]%",
+                    value
+                )),
+            },
+            None => value
+                .parse()
+                .map(MinCores::Count)
+                .map_err(|_| format!("min_cores '{}' is not a core count or percentage", value)),
+        }
+    }
+}
+
+impl Serialize for MinCores {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            MinCores::Count(count) => serializer.serialize_u64(*count as u64),
+            MinCores::Percent(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MinCores {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MinCoresVisitor;
+
+        impl Visitor<'_> for MinCoresVisitor {
+            type Value = MinCores;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a core count or a percentage such as \"25%\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<MinCores, E> {
+                Ok(MinCores::Count(value as usize))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<MinCores, E> {
+                usize::try_from(value)
+                    .map(MinCores::Count)
+                    .map_err(|_| E::custom(format!("min_cores {} must not be negative", value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<MinCores, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MinCoresVisitor)
+    }
+}
+
+/// A named bundle of per-state knobs, defined under `[profiles.<name>]`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    pub core_percentage: u32,
+    pub load_threshold: f32,
+    pub epp: String,
+    pub governor: Option<String>, // None keeps the driver-derived lever
+    pub turbo: Option<bool>,      // None leaves turbo/boost untouched
+    pub max_freq_percent: Option<u32>, // Cap scaling_max_freq at this % of the hardware max
+    pub epp_pcore: Option<String>, // EPP for P-core policies on hybrid CPUs, instead of epp
+    pub epp_ecore: Option<String>, // EPP for E-core policies on hybrid CPUs, instead of epp
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            core_percentage: 100,
+            load_threshold: 80.0,
+            epp: "balance_performance".to_string(),
+            governor: None,
+            turbo: None,
+            max_freq_percent: None,
+            epp_pcore: None,
+            epp_ecore: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub battery_core_percentage: u32,
     pub ac_core_percentage: u32, // Added for AC mode
@@ -8,11 +202,101 @@ pub struct Settings {
     pub check_interval_sec: u64,
     pub cpu_load_threshold: f32,
     pub ac_cpu_load_threshold: f32, // Added for AC mode
-    pub min_cores: usize,
+    pub min_cores: MinCores,        // Absolute count or percentage of all cores
     pub min_change_interval_sec: u64,
     pub load_window_sec: u64,
-    pub battery_epp: String, // Add EPP setting
-    pub ac_epp: String,      // Add EPP setting
+    pub battery_epp: String,               // Add EPP setting
+    pub ac_epp: String,                    // Add EPP setting
+    pub ac_epp_pcore: Option<String>,      // Overrides ac_epp on P-core policies
+    pub ac_epp_ecore: Option<String>,      // Overrides ac_epp on E-core policies
+    pub battery_epp_pcore: Option<String>, // Overrides battery_epp on P-core policies
+    pub battery_epp_ecore: Option<String>, // Overrides battery_epp on E-core policies
+    pub throttle_method: ThrottleMethod,
+    pub inhibit_lock_path: Option<String>, // Hold all cores online while this file exists
+    pub group_cores_by_cache: bool,        // Keep online cores within as few LLC groups as possible
+    pub offline_priority: OfflinePriority,
+    pub status_file: Option<String>, // JSON snapshot rewritten every check interval
+    pub input_idle_sec: Option<u64>, // Park to input_idle_core_percentage after this much input idle
+    pub input_idle_core_percentage: u32,
+    pub input_idle_source: InputIdleSource,
+    pub min_rampup_interval_sec: Option<u64>, // Falls back to min_change_interval_sec
+    pub min_rampdown_interval_sec: Option<u64>, // Falls back to min_change_interval_sec
+    pub ac_turbo: Option<bool>,               // None leaves turbo/boost untouched
+    pub battery_turbo: Option<bool>,          // None leaves turbo/boost untouched
+    pub power_supply_path: String,
+    pub rampup_percentile: Option<f32>, // Use this load percentile instead of the mean to ramp up
+    pub group_cores_by_numa: bool,      // Keep online cores within as few NUMA nodes as possible
+    pub log_dedup_sec: u64,             // Repeat identical permission errors at most this often
+    pub max_history_samples: Option<usize>, // Drop the oldest load samples beyond this count
+    pub respect_cgroup_quota: bool, // Scale against the cgroup v2 cpu.max quota instead of all CPUs
+    pub profiles: HashMap<String, Profile>,
+    pub default_profile: Option<String>, // Used when no state-specific profile is mapped
+    pub ac_profile: Option<String>,
+    pub battery_profile: Option<String>,
+    pub low_battery_profile: Option<String>, // On battery below low_battery_percentage
+    pub low_battery_percentage: f32,
+    pub force_core_count: Option<usize>, // Pin to this many cores, bypassing load-based decisions
+    pub reversal_lockout_sec: Option<u64>, // Hold after a change this long if the next one would reverse it
+    pub load_smoothing: LoadSmoothing,
+    pub manage_cores_from: usize, // Leave CPUs below this index alone (0 manages all but protected_cores)
+    pub post_release_grace_sec: Option<u64>, // After a hold releases, block ramp-down this long
+    pub unknown_power_means: AssumedPowerState, // What an unreadable power state is treated as
+    pub p_core_transition_delay_ms: Option<u64>, // Falls back to transition_delay_ms
+    pub e_core_transition_delay_ms: Option<u64>, // Falls back to transition_delay_ms
+    pub keep_one_core_per_numa_node: bool, // Never leave a NUMA node without an online CPU
+    pub power_envelope_watts: Option<f32>, // Step cores down while RAPL package power exceeds this
+    pub skip_initial_rampdowns: u32, // Ignore ramp-down decisions for this many first iterations
+    pub max_decision_staleness_sec: Option<u64>, // Wake before check_interval so samples are never older than this
+    pub no_topology_core_percentage: Option<u32>, // Core percentage when topology can't be read
+    pub rampup_duty_cycle: Option<f32>, // Ramp up only if load was above the threshold this fraction of the window
+    pub log_resolved_config: bool,      // Log the effective settings as TOML at startup
+    pub epp_core_change_gap_ms: Option<u64>, // Delay between EPP and core count changes in one iteration
+    pub epp_core_change_order: EppCoreOrder,
+    pub on_max_cores_command: Option<String>, // Run via sh -c when all cores come online
+    pub on_min_cores_command: Option<String>, // Run via sh -c when reaching min_cores
+    pub track_power_durations: bool,          // Accumulate time spent on AC and battery
+    pub power_durations_by_core_count: bool,  // Also split those durations by online core count
+    pub epp_transition_steps: u32, // Intermediate EPP values on a power change (0 = jump)
+    pub epp_transition_interval_ms: u64, // Delay between EPP transition steps
+    pub max_rampdown_per_decision: Option<usize>, // Most cores removed in one iteration
+    pub max_rampup_per_decision: Option<usize>, // Most cores added in one iteration
+    pub max_fan_rpm: Option<u32>,  // Cap cores while any fan spins faster than this
+    pub acoustic_core_percentage: u32, // Core percentage allowed while fans are over max_fan_rpm
+    pub percentage_rounding: PercentageRounding, // How core_percentage limits round
+    pub rampup_sustain_sec: Option<u64>, // Load must stay above the ramp-up threshold this long
+    pub webhook_url: Option<String>, // POST change events here (webhook feature)
+    pub auto_reload: bool,         // Reload when a config file's mtime changes
+    pub per_core_smoothing_alpha: f32, // EWMA weight of the newest per-core sample (1.0 = raw)
+    pub exit_if_no_control: bool,  // Exit with status 3 when nothing is writable
+    pub load_core_table: Vec<(f32, usize)>, // [load, cores] breakpoints replacing the threshold logic
+    pub target_cores_url: Option<String>, // Poll for the core count to run (remote_target feature)
+    pub deadman_path: Option<String>,     // Heartbeat and restore hint for external watchdogs
+    pub interpolate_by_capacity: bool,    // Blend battery and AC core percentages by charge
+    pub capacity_band_low: u8,            // Charge at or below which the battery percentage applies
+    pub capacity_band_high: u8,           // Charge at or above which the AC percentage applies
+    pub low_battery_core_percentage: Option<u32>, // Core percentage on battery below low_battery_percentage
+    pub dry_run: bool,    // Log intended sysfs writes instead of making them
+    pub core_step: usize, // Cores added or removed per load-based decision
+    pub scaling_mode: ScalingMode, // Fixed core_step or a step proportional to the load's distance from the threshold
+    pub scale_up_threshold_pct: f32, // Ramp up above this % of the load threshold
+    pub scale_down_threshold_pct: f32, // Ramp down below this % of the load threshold
+    pub ac_governor: Option<String>, // Governor on AC, written before EPP (None = derive from EPP where needed)
+    pub battery_governor: Option<String>, // Governor on battery, written before EPP
+    pub default_governor: Option<String>, // Governor restored on shutdown (None = leave or derive from EPP)
+    pub state_file: Option<String>, // Offlined cores and original EPP, undone on the next start after a crash
+    pub cpu_hotplug_aware: bool,    // Re-enumerate cores when a cached one disappears
+    pub status_socket_path: Option<String>, // Unix socket answering 'status' queries
+    pub metrics_addr: Option<String>, // Serve Prometheus /metrics here (metrics feature)
+    pub thermal_limit_celsius: Option<f32>, // Shed cores while the hottest hwmon sensor is above this
+    pub thermal_core_reduction: u32, // Cores removed per decision while over thermal_limit_celsius
+    pub min_core_uptime_sec: u64, // Keep a core we onlined up this long before offlining it again
+    pub load_ewma_alpha: f32,     // Weight of the newest sample with load_smoothing = "ewma"
+    pub log_backend: LogBackend,  // Where log records go (journald needs the journald feature)
+    pub protected_cores: Vec<usize>, // Never taken offline, on top of CPUs without an online file
+    pub power_transition_cooldown_sec: Option<u64>, // Hold the core count this long after AC/battery changes
+    pub cpu_sample_interval_ms: u64, // Baseline sample before the first load reading, at most 1000
+    pub load_percentile: f32, // Percentile of the window used with load_smoothing = "percentile"
+    pub prefer_smt_offline_first: bool, // Offline SMT sibling threads before whole cores
 }
 
 impl Default for Settings {
@@ -24,11 +308,561 @@ impl Default for Settings {
             check_interval_sec: 5,
             cpu_load_threshold: 45.0,
             ac_cpu_load_threshold: 80.0,
-            min_cores: 2,
+            min_cores: MinCores::Count(2),
             min_change_interval_sec: 15, // Reduced default
             load_window_sec: 30,
             battery_epp: "balance_power".to_string(), // Set default
             ac_epp: "balance_performance".to_string(), // Set default
+            ac_epp_pcore: None,
+            ac_epp_ecore: None,
+            battery_epp_pcore: None,
+            battery_epp_ecore: None,
+            throttle_method: ThrottleMethod::Offline,
+            inhibit_lock_path: None,
+            group_cores_by_cache: false,
+            offline_priority: OfflinePriority::Efficiency,
+            status_file: None,
+            input_idle_sec: None,
+            input_idle_core_percentage: 25,
+            input_idle_source: InputIdleSource::Logind,
+            min_rampup_interval_sec: None,
+            min_rampdown_interval_sec: None,
+            ac_turbo: None,
+            battery_turbo: None,
+            power_supply_path: "/sys/class/power_supply/".to_string(),
+            rampup_percentile: None,
+            group_cores_by_numa: false,
+            log_dedup_sec: 300,
+            max_history_samples: None,
+            respect_cgroup_quota: false,
+            profiles: HashMap::new(),
+            default_profile: None,
+            ac_profile: None,
+            battery_profile: None,
+            low_battery_profile: None,
+            low_battery_percentage: 20.0,
+            force_core_count: None,
+            reversal_lockout_sec: None,
+            load_smoothing: LoadSmoothing::Mean,
+            manage_cores_from: 0,
+            post_release_grace_sec: None,
+            unknown_power_means: AssumedPowerState::Ac,
+            p_core_transition_delay_ms: None,
+            e_core_transition_delay_ms: None,
+            keep_one_core_per_numa_node: true,
+            power_envelope_watts: None,
+            skip_initial_rampdowns: 1,
+            max_decision_staleness_sec: None,
+            no_topology_core_percentage: None,
+            rampup_duty_cycle: None,
+            log_resolved_config: false,
+            epp_core_change_gap_ms: None,
+            epp_core_change_order: EppCoreOrder::EppFirst,
+            on_max_cores_command: None,
+            on_min_cores_command: None,
+            track_power_durations: false,
+            power_durations_by_core_count: false,
+            epp_transition_steps: 0,
+            epp_transition_interval_ms: 500,
+            max_rampdown_per_decision: None,
+            max_rampup_per_decision: None,
+            max_fan_rpm: None,
+            acoustic_core_percentage: 50,
+            percentage_rounding: PercentageRounding::Ceil,
+            rampup_sustain_sec: None,
+            webhook_url: None,
+            auto_reload: false,
+            per_core_smoothing_alpha: 0.5,
+            exit_if_no_control: false,
+            load_core_table: Vec::new(),
+            target_cores_url: None,
+            deadman_path: None,
+            interpolate_by_capacity: false,
+            capacity_band_low: 20,
+            capacity_band_high: 80,
+            low_battery_core_percentage: None,
+            dry_run: false,
+            core_step: 2,
+            scaling_mode: ScalingMode::Fixed,
+            scale_up_threshold_pct: 120.0,
+            scale_down_threshold_pct: 80.0,
+            ac_governor: None,
+            battery_governor: None,
+            default_governor: None,
+            state_file: Some("/var/lib/observer/state.json".to_string()),
+            cpu_hotplug_aware: false,
+            status_socket_path: None,
+            metrics_addr: None,
+            thermal_limit_celsius: None,
+            thermal_core_reduction: 2,
+            min_core_uptime_sec: 0,
+            load_ewma_alpha: 0.3,
+            log_backend: LogBackend::Auto,
+            protected_cores: vec![0],
+            power_transition_cooldown_sec: None,
+            cpu_sample_interval_ms: 200,
+            load_percentile: 75.0,
+            prefer_smt_offline_first: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The settings as a TOML config file, for logging what is actually in effect.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Top-level keys whose values differ between `self` and `other`, sorted.
+    pub fn changed_keys(&self, other: &Settings) -> Vec<String> {
+        let (Ok(toml::Value::Table(old)), Ok(toml::Value::Table(new))) =
+            (toml::Value::try_from(self), toml::Value::try_from(other))
+        else {
+            return Vec::new();
+        };
+        let mut keys: Vec<String> = old
+            .keys()
+            .chain(new.keys())
+            .filter(|key| old.get(*key) != new.get(*key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    /// Checks values that parse fine but make no sense: percentages outside 1-100,
+    /// min_cores of 0, a check interval of 0, load thresholds outside 0-100% per logical
+    /// CPU, or a change interval longer than the load window. Returns one message per violation.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let violations = self.clone().clamp_invalid();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Clamps the fields `validate` rejects to the nearest safe value, returning what was
+    /// changed.
+    pub fn clamp_invalid(&mut self) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        for (name, value) in [
+            ("battery_core_percentage", &mut self.battery_core_percentage),
+            ("ac_core_percentage", &mut self.ac_core_percentage),
+            (
+                "input_idle_core_percentage",
+                &mut self.input_idle_core_percentage,
+            ),
+            (
+                "acoustic_core_percentage",
+                &mut self.acoustic_core_percentage,
+            ),
+        ] {
+            clamp_percentage(name, value, &mut violations);
+        }
+        for (name, value) in [
+            (
+                "no_topology_core_percentage",
+                &mut self.no_topology_core_percentage,
+            ),
+            (
+                "low_battery_core_percentage",
+                &mut self.low_battery_core_percentage,
+            ),
+        ] {
+            if let Some(value) = value {
+                clamp_percentage(name, value, &mut violations);
+            }
+        }
+        for (name, profile) in &mut self.profiles {
+            clamp_percentage(
+                &format!("profiles.{}.core_percentage", name),
+                &mut profile.core_percentage,
+                &mut violations,
+            );
+            clamp_threshold(
+                &format!("profiles.{}.load_threshold", name),
+                &mut profile.load_threshold,
+                &mut violations,
+            );
+        }
+        for (name, value) in [
+            ("cpu_load_threshold", &mut self.cpu_load_threshold),
+            ("ac_cpu_load_threshold", &mut self.ac_cpu_load_threshold),
+        ] {
+            clamp_threshold(name, value, &mut violations);
         }
+
+        if !(self.scale_down_threshold_pct >= 0.0
+            && self.scale_down_threshold_pct < self.scale_up_threshold_pct)
+        {
+            violations.push(format!(
+                "scale_down_threshold_pct = {} must be within 0 and scale_up_threshold_pct = {}, using 80/120",
+                self.scale_down_threshold_pct, self.scale_up_threshold_pct
+            ));
+            self.scale_up_threshold_pct = 120.0;
+            self.scale_down_threshold_pct = 80.0;
+        }
+
+        if !(self.load_ewma_alpha > 0.0 && self.load_ewma_alpha <= 1.0) {
+            violations.push(format!(
+                "load_ewma_alpha = {} must be within (0, 1], using 1",
+                self.load_ewma_alpha
+            ));
+            self.load_ewma_alpha = 1.0;
+        }
+
+        // The warm-up sleep runs before the loop checks for shutdown, so keep it short
+        if self.cpu_sample_interval_ms > 1000 {
+            violations.push(format!(
+                "cpu_sample_interval_ms = {} must be at most 1000, using 1000",
+                self.cpu_sample_interval_ms
+            ));
+            self.cpu_sample_interval_ms = 1000;
+        }
+
+        if !(self.load_percentile > 0.0 && self.load_percentile <= 100.0) {
+            violations.push(format!(
+                "load_percentile = {} must be within (0, 100], using 50",
+                self.load_percentile
+            ));
+            self.load_percentile = 50.0;
+        }
+
+        if self.check_interval_sec == 0 {
+            violations.push("check_interval_sec must be at least 1, using 1".to_string());
+            self.check_interval_sec = 1;
+        }
+
+        if self.core_step == 0 {
+            violations.push("core_step must be at least 1, using 1".to_string());
+            self.core_step = 1;
+        }
+
+        match self.min_cores {
+            MinCores::Count(0) => {
+                violations.push("min_cores must be at least 1, using 1".to_string());
+                self.min_cores = MinCores::Count(1);
+            }
+            MinCores::Percent(pct) if pct > 100.0 => {
+                violations.push(format!(
+                    "min_cores = {}% must be at most 100%, using 100%",
+                    pct
+                ));
+                self.min_cores = MinCores::Percent(100.0);
+            }
+            // 0%, negative and NaN would resolve to a single core anyway, so say so
+            MinCores::Percent(pct) if pct.is_nan() || pct <= 0.0 => {
+                violations.push(format!("min_cores = {}% must be above 0%, using 1", pct));
+                self.min_cores = MinCores::Count(1);
+            }
+            _ => {}
+        }
+
+        if self.min_change_interval_sec > self.load_window_sec {
+            violations.push(format!(
+                "min_change_interval_sec = {} is longer than load_window_sec = {}, using {}",
+                self.min_change_interval_sec, self.load_window_sec, self.load_window_sec
+            ));
+            self.min_change_interval_sec = self.load_window_sec;
+        }
+        violations
+    }
+
+    /// Built-in profile derived from the flat `ac_*` keys.
+    fn builtin_ac_profile(&self) -> Profile {
+        Profile {
+            core_percentage: self.ac_core_percentage,
+            load_threshold: self.ac_cpu_load_threshold,
+            epp: self.ac_epp.clone(),
+            governor: self.ac_governor.clone(),
+            turbo: self.ac_turbo,
+            max_freq_percent: None,
+            epp_pcore: self.ac_epp_pcore.clone(),
+            epp_ecore: self.ac_epp_ecore.clone(),
+        }
+    }
+
+    /// Built-in profile derived from the flat battery keys.
+    fn builtin_battery_profile(&self) -> Profile {
+        Profile {
+            core_percentage: self.battery_core_percentage,
+            load_threshold: self.cpu_load_threshold,
+            epp: self.battery_epp.clone(),
+            governor: self.battery_governor.clone(),
+            turbo: self.battery_turbo,
+            max_freq_percent: None,
+            epp_pcore: self.battery_epp_pcore.clone(),
+            epp_ecore: self.battery_epp_ecore.clone(),
+        }
+    }
+
+    /// Looks up a profile for `observer apply`: `[profiles.<name>]` from the config, or the
+    /// built-in `performance`, `balanced` and `powersave` presets.
+    pub fn profile_by_name(&self, name: &str) -> Option<Profile> {
+        if let Some(profile) = self.profiles.get(name) {
+            return Some(profile.clone());
+        }
+        let (core_percentage, epp, turbo) = match name {
+            "performance" => (100, "performance", Some(true)),
+            "balanced" => (75, "balance_performance", None),
+            "powersave" => (50, "power", Some(false)),
+            _ => return None,
+        };
+        Some(Profile {
+            core_percentage,
+            epp: epp.to_string(),
+            turbo,
+            ..Profile::default()
+        })
+    }
+
+    fn named_profile(&self, name: Option<&String>) -> Option<&Profile> {
+        let name = name?;
+        let profile = self.profiles.get(name);
+        if profile.is_none() {
+            warn!("Profile '{}' is not defined, using built-in settings", name);
+        }
+        profile
+    }
+
+    /// Maps an Unknown power state to what `unknown_power_means` says it is.
+    pub fn resolve_power_state(&self, power: PowerState) -> PowerState {
+        match (power, self.unknown_power_means) {
+            (PowerState::Unknown, AssumedPowerState::Ac) => PowerState::AC,
+            (PowerState::Unknown, AssumedPowerState::Battery) => PowerState::Battery,
+            (power, _) => power,
+        }
+    }
+
+    /// Resolves the profile for the current power state. Named profiles from the
+    /// `*_profile` mapping win; without them the flat AC/battery keys apply as before.
+    pub fn active_profile(&self, power: PowerState, battery_pct: Option<f32>) -> Cow<'_, Profile> {
+        let power = self.resolve_power_state(power);
+        let low_battery = battery_pct.is_some_and(|pct| pct < self.low_battery_percentage);
+        let named = match power {
+            PowerState::Battery if low_battery && self.low_battery_profile.is_some() => {
+                self.named_profile(self.low_battery_profile.as_ref())
+            }
+            PowerState::Battery => self.named_profile(self.battery_profile.as_ref()),
+            PowerState::AC | PowerState::NoSupply => self.named_profile(self.ac_profile.as_ref()),
+            PowerState::Unknown => None,
+        }
+        .or_else(|| self.named_profile(self.default_profile.as_ref()));
+
+        match (named, power) {
+            (Some(profile), _) => Cow::Borrowed(profile),
+            (None, PowerState::Battery) => Cow::Owned(self.builtin_battery_profile()),
+            (None, _) => Cow::Owned(self.builtin_ac_profile()),
+        }
+    }
+}
+
+fn clamp_percentage(name: &str, value: &mut u32, violations: &mut Vec<String>) {
+    let clamped = (*value).clamp(1, 100);
+    if clamped != *value {
+        violations.push(format!(
+            "{} = {} must be within 1-100, using {}",
+            name, value, clamped
+        ));
+        *value = clamped;
+    }
+}
+
+fn clamp_threshold(name: &str, value: &mut f32, violations: &mut Vec<String>) {
+    if (0.0..=100.0).contains(value) {
+        return;
+    }
+    let clamped = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 100.0)
+    };
+    violations.push(format!(
+        "{} = {} must be within 0-100, using {}",
+        name, value, clamped
+    ));
+    *value = clamped;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_profile_falls_back_to_flat_keys() {
+        let settings = Settings::default();
+        let battery = settings.active_profile(PowerState::Battery, None);
+        assert_eq!(battery.core_percentage, settings.battery_core_percentage);
+        assert_eq!(battery.epp, settings.battery_epp);
+        let ac = settings.active_profile(PowerState::AC, None);
+        assert_eq!(ac.load_threshold, settings.ac_cpu_load_threshold);
+        assert_eq!(ac.governor, None);
+
+        let settings = Settings {
+            ac_governor: Some("performance".to_string()),
+            battery_governor: Some("powersave".to_string()),
+            ac_epp_pcore: Some("performance".to_string()),
+            battery_epp_ecore: Some("power".to_string()),
+            ..Settings::default()
+        };
+        let battery = settings.active_profile(PowerState::Battery, None);
+        assert_eq!(battery.governor.as_deref(), Some("powersave"));
+        assert_eq!(battery.epp_pcore, None);
+        assert_eq!(battery.epp_ecore.as_deref(), Some("power"));
+        let ac = settings.active_profile(PowerState::AC, None);
+        assert_eq!(ac.governor.as_deref(), Some("performance"));
+        assert_eq!(ac.epp_pcore.as_deref(), Some("performance"));
+    }
+
+    #[test]
+    fn test_profile_by_name() {
+        let mut settings = Settings::default();
+        let performance = settings.profile_by_name("performance").unwrap();
+        assert_eq!(performance.core_percentage, 100);
+        assert_eq!(performance.epp, "performance");
+        assert!(settings.profile_by_name("turbo").is_none());
+
+        // A user profile replaces the built-in one of the same name
+        let custom = Profile {
+            core_percentage: 25,
+            ..Profile::default()
+        };
+        settings
+            .profiles
+            .insert("powersave".to_string(), custom.clone());
+        assert_eq!(settings.profile_by_name("powersave"), Some(custom));
+    }
+
+    #[test]
+    fn test_to_toml_round_trips() {
+        let mut settings = Settings {
+            force_core_count: Some(6),
+            ..Settings::default()
+        };
+        settings
+            .profiles
+            .insert("quiet".to_string(), Profile::default());
+
+        let toml = settings.to_toml().unwrap();
+        let parsed: Settings = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.force_core_count, Some(6));
+        assert_eq!(parsed.battery_epp, settings.battery_epp);
+        assert_eq!(parsed.profiles["quiet"], Profile::default());
+    }
+
+    #[test]
+    fn test_validate_clamps_impossible_values() {
+        assert!(Settings::default().validate().is_ok());
+
+        let mut settings = Settings {
+            battery_core_percentage: 500,
+            min_cores: MinCores::Count(0),
+            cpu_load_threshold: -10.0,
+            min_change_interval_sec: 60,
+            load_window_sec: 30,
+            cpu_sample_interval_ms: 5000,
+            check_interval_sec: 0,
+            ..Settings::default()
+        };
+        assert_eq!(settings.validate().unwrap_err().len(), 6);
+
+        settings.clamp_invalid();
+        assert_eq!(settings.battery_core_percentage, 100);
+        assert_eq!(settings.cpu_sample_interval_ms, 1000);
+        assert_eq!(settings.min_cores, MinCores::Count(1));
+        assert_eq!(settings.cpu_load_threshold, 0.0);
+        assert_eq!(settings.min_change_interval_sec, 30);
+        assert_eq!(settings.check_interval_sec, 1);
+        assert!(settings.validate().is_ok());
+
+        for pct in [0.0, -5.0, f32::NAN] {
+            let mut settings = Settings {
+                min_cores: MinCores::Percent(pct),
+                ..Settings::default()
+            };
+            assert_eq!(settings.clamp_invalid().len(), 1);
+            assert_eq!(settings.min_cores, MinCores::Count(1));
+        }
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let settings = Settings::default();
+        assert!(settings.changed_keys(&settings.clone()).is_empty());
+        let edited = Settings {
+            min_cores: MinCores::Percent(25.0),
+            max_fan_rpm: Some(2000),
+            ..Settings::default()
+        };
+        assert_eq!(
+            settings.changed_keys(&edited),
+            vec!["max_fan_rpm", "min_cores"]
+        );
+    }
+
+    #[test]
+    fn test_min_cores_count_or_percentage() {
+        let parsed: Settings = toml::from_str("min_cores = 3").unwrap();
+        assert_eq!(parsed.min_cores, MinCores::Count(3));
+        assert_eq!(parsed.min_cores.resolve(16), 3);
+
+        let parsed: Settings = toml::from_str("min_cores = \"25%\"").unwrap();
+        assert_eq!(parsed.min_cores, MinCores::Percent(25.0));
+        assert_eq!(parsed.min_cores.resolve(16), 4);
+        assert_eq!(parsed.min_cores.resolve(6), 2);
+        assert_eq!(parsed.min_cores.resolve(2), 1);
+        assert_eq!(MinCores::Count(0).resolve(8), 1);
+
+        assert!(toml::from_str::<Settings>("min_cores = \"150%\"").is_err());
+        assert!(toml::from_str::<Settings>("min_cores = \"0%\"").is_err());
+        assert!(toml::from_str::<Settings>("min_cores = \"-5%\"").is_err());
+        assert!(toml::from_str::<Settings>("min_cores = -1").is_err());
+        let toml = parsed.to_toml().unwrap();
+        assert!(toml.contains("min_cores = \"25%\""));
+    }
+
+    #[test]
+    fn test_active_profile_mapping() {
+        let quiet = Profile {
+            core_percentage: 25,
+            epp: "power".to_string(),
+            ..Profile::default()
+        };
+        let gaming = Profile {
+            core_percentage: 100,
+            epp: "performance".to_string(),
+            ..Profile::default()
+        };
+        let mut settings = Settings {
+            ac_profile: Some("gaming".to_string()),
+            low_battery_profile: Some("quiet".to_string()),
+            default_profile: Some("gaming".to_string()),
+            ..Settings::default()
+        };
+        settings.profiles.insert("quiet".to_string(), quiet.clone());
+        settings
+            .profiles
+            .insert("gaming".to_string(), gaming.clone());
+
+        assert_eq!(*settings.active_profile(PowerState::AC, None), gaming);
+        assert_eq!(*settings.active_profile(PowerState::Unknown, None), gaming);
+        settings.unknown_power_means = AssumedPowerState::Battery;
+        assert_eq!(
+            *settings.active_profile(PowerState::Unknown, Some(10.0)),
+            quiet
+        );
+        settings.unknown_power_means = AssumedPowerState::Ac;
+        assert_eq!(
+            *settings.active_profile(PowerState::Battery, Some(10.0)),
+            quiet
+        );
+        // No battery_profile mapping, so the default profile applies above the threshold
+        assert_eq!(
+            *settings.active_profile(PowerState::Battery, Some(80.0)),
+            gaming
+        );
     }
 }
@@ -0,0 +1,322 @@
+use super::error::CoreError;
+use super::topology::parse_cpu_list;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CPUFREQ_SYSFS_PATH: &str = "/sys/devices/system/cpu/cpufreq";
+
+/// Lists the cpufreq policy numbers that expose `energy_performance_preference`, sorted.
+/// Empty when cpufreq isn't available (including on non-Linux systems).
+pub fn list_epp_policies() -> Vec<usize> {
+    list_epp_policies_in(Path::new(CPUFREQ_SYSFS_PATH))
+}
+
+pub fn list_epp_policies_in(cpufreq_path: &Path) -> Vec<usize> {
+    let mut policies: Vec<usize> = match fs::read_dir(cpufreq_path) {
+        Ok(entries) => entries
+            .flatten()
+            // fs::metadata follows symlinks, so symlinked policy directories still count
+            .filter(|entry| fs::metadata(entry.path()).is_ok_and(|m| m.is_dir()))
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .strip_prefix("policy")
+                    .and_then(|n| n.parse().ok())
+            })
+            .filter(|policy| epp_path(cpufreq_path, *policy).exists())
+            .collect(),
+        Err(e) => {
+            debug!("Could not read {}: {}", cpufreq_path.display(), e);
+            Vec::new()
+        }
+    };
+    policies.sort_unstable();
+    policies
+}
+
+/// The logical CPUs governed by a cpufreq policy, from `policyN/affected_cpus`. Empty when
+/// the file can't be read.
+pub fn policy_cpus(policy: usize) -> Vec<usize> {
+    policy_cpus_in(Path::new(CPUFREQ_SYSFS_PATH), policy)
+}
+
+pub fn policy_cpus_in(cpufreq_path: &Path, policy: usize) -> Vec<usize> {
+    let path = cpufreq_path
+        .join(format!("policy{}", policy))
+        .join("affected_cpus");
+    // affected_cpus is space-separated, unlike the comma-separated cpulists elsewhere
+    fs::read_to_string(path)
+        .map(|cpus| parse_cpu_list(&cpus.split_whitespace().collect::<Vec<_>>().join(",")))
+        .unwrap_or_default()
+}
+
+/// Sets the EPP hint for a single cpufreq policy. Accepts named hints or a performance
+/// percentage such as `"70%"`.
+pub fn set_epp_for_policy(policy: usize, hint: &str) -> Result<(), CoreError> {
+    set_epp_for_policy_in(Path::new(CPUFREQ_SYSFS_PATH), policy, hint)
+}
+
+pub fn set_epp_for_policy_in(
+    cpufreq_path: &Path,
+    policy: usize,
+    hint: &str,
+) -> Result<(), CoreError> {
+    let (path, value) = resolve_epp_in(cpufreq_path, policy, hint)?;
+    fs::write(&path, &value).map_err(|e| CoreError::from_io(path.clone(), e))?;
+    debug!("Set EPP for policy{} to '{}'", policy, value);
+    Ok(())
+}
+
+/// Reads the current EPP hint of a single cpufreq policy.
+pub fn read_epp_for_policy(policy: usize) -> Result<String, CoreError> {
+    read_epp_for_policy_in(Path::new(CPUFREQ_SYSFS_PATH), policy)
+}
+
+pub fn read_epp_for_policy_in(cpufreq_path: &Path, policy: usize) -> Result<String, CoreError> {
+    let path = epp_path(cpufreq_path, policy);
+    let hint = fs::read_to_string(&path).map_err(|e| CoreError::from_io(path.clone(), e))?;
+    Ok(hint.trim().to_string())
+}
+
+/// Whether setting `hint` would change the policy's current EPP, without writing anything.
+pub fn epp_would_change(policy: usize, hint: &str) -> Result<bool, CoreError> {
+    epp_would_change_in(Path::new(CPUFREQ_SYSFS_PATH), policy, hint)
+}
+
+pub fn epp_would_change_in(
+    cpufreq_path: &Path,
+    policy: usize,
+    hint: &str,
+) -> Result<bool, CoreError> {
+    let (path, value) = resolve_epp_in(cpufreq_path, policy, hint)?;
+    let current = fs::read_to_string(&path).map_err(|e| CoreError::from_io(path.clone(), e))?;
+    Ok(current.trim() != value)
+}
+
+/// The EPP file for `policy` and the value `hint` would be written as.
+fn resolve_epp_in(
+    cpufreq_path: &Path,
+    policy: usize,
+    hint: &str,
+) -> Result<(PathBuf, String), CoreError> {
+    let mut value = epp_value(hint).map_err(CoreError::InvalidValue)?;
+    let path = epp_path(cpufreq_path, policy);
+    if !path.exists() {
+        return Err(CoreError::NotFound(path));
+    }
+    // Hybrid chips can offer different hints per policy, so check this policy's own list
+    if let Some(available) = available_preferences(cpufreq_path, policy) {
+        match supported_hint(&available, &value) {
+            Some(supported) if supported != value => {
+                debug!(
+                    "policy{} does not offer '{}', using '{}' instead",
+                    policy, value, supported
+                );
+                value = supported;
+            }
+            Some(_) => {}
+            None => {
+                return Err(CoreError::Unsupported(format!(
+                    "policy{} offers no equivalent of EPP '{}' (available: {})",
+                    policy,
+                    value,
+                    available.join(" ")
+                )))
+            }
+        }
+    }
+    Ok((path, value))
+}
+
+fn epp_path(cpufreq_path: &Path, policy: usize) -> PathBuf {
+    cpufreq_path
+        .join(format!("policy{}", policy))
+        .join("energy_performance_preference")
+}
+
+/// The hints listed in a policy's `energy_performance_available_preferences`, or None when
+/// the policy doesn't publish the list.
+fn available_preferences(cpufreq_path: &Path, policy: usize) -> Option<Vec<String>> {
+    let path = cpufreq_path
+        .join(format!("policy{}", policy))
+        .join("energy_performance_available_preferences");
+    let list = fs::read_to_string(path).ok()?;
+    Some(list.split_whitespace().map(str::to_string).collect())
+}
+
+/// Picks what to write for `value` given a policy's available hints: the hint itself when
+/// offered, the closest offered hint on the performance/power ladder otherwise. Raw numbers
+/// aren't listed by the kernel and pass through unchanged.
+fn supported_hint(available: &[String], value: &str) -> Option<String> {
+    if value.parse::<u8>().is_ok() || available.iter().any(|hint| hint == value) {
+        return Some(value.to_string());
+    }
+    let (_, raw) = EPP_LADDER.iter().find(|(name, _)| *name == value)?;
+    EPP_LADDER
+        .iter()
+        .filter(|(name, _)| available.iter().any(|hint| hint == name))
+        .min_by_key(|(_, other)| (i16::from(*raw) - i16::from(*other)).abs())
+        .map(|(name, _)| name.to_string())
+}
+
+/// Translates a percentage hint such as `"70%"` (performance preference) into the raw 0-255
+/// EPP scale, where 0 is maximum performance. Named hints pass through unchanged.
+pub(crate) fn epp_value(hint: &str) -> Result<String, String> {
+    let Some(percentage) = hint.trim().strip_suffix('%') else {
+        return Ok(hint.to_string());
+    };
+    let percentage: f32 = percentage
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid EPP percentage '{}'", hint))?;
+    if !(0.0..=100.0).contains(&percentage) {
+        return Err(format!("EPP percentage '{}' must be within 0-100%", hint));
+    }
+    Ok((((100.0 - percentage) * 255.0 / 100.0) as u8).to_string())
+}
+
+/// Named hints in order from most performant to most power-saving, with the raw values
+/// intel_pstate maps them to.
+const EPP_LADDER: [(&str, u8); 4] = [
+    ("performance", 0),
+    ("balance_performance", 128),
+    ("balance_power", 192),
+    ("power", 255),
+];
+
+/// Intermediate hints to pass through between `from` and `to`, excluding both ends. Two
+/// named hints step through the named values between them; otherwise the raw values are
+/// interpolated in `steps` steps. Empty when `steps` is 0 or either hint is unrecognised.
+pub(crate) fn transition_steps(from: &str, to: &str, steps: u32) -> Vec<String> {
+    if steps == 0 || from == to {
+        return Vec::new();
+    }
+    let ladder_index = |hint: &str| EPP_LADDER.iter().position(|(name, _)| *name == hint);
+    if let (Some(from), Some(to)) = (ladder_index(from), ladder_index(to)) {
+        let names: Vec<String> = if from < to {
+            (from + 1..to)
+                .map(|i| EPP_LADDER[i].0.to_string())
+                .collect()
+        } else {
+            (to + 1..from)
+                .rev()
+                .map(|i| EPP_LADDER[i].0.to_string())
+                .collect()
+        };
+        return names;
+    }
+
+    let raw = |hint: &str| -> Option<u8> {
+        match ladder_index(hint) {
+            Some(i) => Some(EPP_LADDER[i].1),
+            None => epp_value(hint).ok()?.parse().ok(),
+        }
+    };
+    let (Some(from), Some(to)) = (raw(from), raw(to)) else {
+        return Vec::new();
+    };
+    let (from, to) = (i32::from(from), i32::from(to));
+    let mut values: Vec<String> = (1..=steps as i32)
+        .map(|i| (from + (to - from) * i / (steps as i32 + 1)).to_string())
+        .collect();
+    values.dedup();
+    values.retain(|v| *v != from.to_string() && *v != to.to_string());
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transition_steps() {
+        assert_eq!(
+            transition_steps("performance", "power", 3),
+            vec!["balance_performance", "balance_power"]
+        );
+        assert_eq!(
+            transition_steps("power", "balance_performance", 3),
+            vec!["balance_power"]
+        );
+        assert_eq!(transition_steps("0", "255", 0), Vec::<String>::new());
+        assert_eq!(
+            transition_steps("100%", "power", 4),
+            vec!["51", "102", "153", "204"]
+        );
+        assert_eq!(
+            transition_steps("default", "power", 4),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_epp_percentage_mapping() {
+        assert_eq!(epp_value("balance_power").unwrap(), "balance_power");
+        assert_eq!(epp_value("70%").unwrap(), "76");
+        assert_eq!(epp_value("100%").unwrap(), "0");
+        assert_eq!(epp_value("0%").unwrap(), "255");
+        assert!(epp_value("150%").is_err());
+        assert!(epp_value("fast%").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_per_policy_epp() {
+        let root =
+            std::env::temp_dir().join(format!("observer-epp-policies-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for policy in [0, 1, 4] {
+            fs::create_dir_all(root.join(format!("policy{}", policy))).unwrap();
+        }
+        for policy in [0, 4] {
+            fs::write(epp_path(&root, policy), "balance_performance").unwrap();
+        }
+        // A policy presented as a symlink and a stray file that only looks like a policy
+        let target = root.join("real-policy6");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("energy_performance_preference"), "power").unwrap();
+        std::os::unix::fs::symlink(&target, root.join("policy6")).unwrap();
+        fs::write(root.join("policy9"), "").unwrap();
+
+        assert_eq!(list_epp_policies_in(&root), vec![0, 4, 6]);
+        assert!(epp_would_change_in(&root, 4, "power").unwrap());
+        set_epp_for_policy_in(&root, 4, "power").unwrap();
+        assert_eq!(fs::read_to_string(epp_path(&root, 4)).unwrap(), "power");
+        assert!(!epp_would_change_in(&root, 4, "power").unwrap());
+        assert_eq!(read_epp_for_policy_in(&root, 4).unwrap(), "power");
+        fs::write(root.join("policy4/affected_cpus"), "4 5\n").unwrap();
+        assert_eq!(policy_cpus_in(&root, 4), vec![4, 5]);
+        assert!(policy_cpus_in(&root, 0).is_empty());
+        assert_eq!(
+            fs::read_to_string(epp_path(&root, 0)).unwrap(),
+            "balance_performance"
+        );
+        assert!(matches!(
+            set_epp_for_policy_in(&root, 1, "power"),
+            Err(CoreError::NotFound(_))
+        ));
+        assert!(matches!(
+            set_epp_for_policy_in(&root, 0, "120%"),
+            Err(CoreError::InvalidValue(_))
+        ));
+
+        // policy4 lacks balance_power, so the nearest offered hint is used instead
+        fs::write(
+            root.join("policy4/energy_performance_available_preferences"),
+            "default performance balance_performance power\n",
+        )
+        .unwrap();
+        set_epp_for_policy_in(&root, 4, "balance_power").unwrap();
+        assert_eq!(fs::read_to_string(epp_path(&root, 4)).unwrap(), "power");
+        set_epp_for_policy_in(&root, 4, "64").unwrap();
+        assert_eq!(fs::read_to_string(epp_path(&root, 4)).unwrap(), "64");
+        assert!(matches!(
+            set_epp_for_policy_in(&root, 4, "quiet"),
+            Err(CoreError::Unsupported(_))
+        ));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
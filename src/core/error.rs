@@ -0,0 +1,40 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors from direct sysfs control exposed through the library API.
+#[derive(Debug)]
+pub enum CoreError {
+    PermissionDenied(PathBuf),
+    NotFound(PathBuf),
+    InvalidValue(String),
+    Unsupported(String), // Valid value the target (e.g. one cpufreq policy) doesn't accept
+    Io(PathBuf, io::Error),
+}
+
+impl CoreError {
+    /// Maps an I/O error on `path`, keeping permission and missing-file cases distinct.
+    pub fn from_io(path: PathBuf, e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => CoreError::PermissionDenied(path),
+            io::ErrorKind::NotFound => CoreError::NotFound(path),
+            _ => CoreError::Io(path, e),
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::PermissionDenied(path) => {
+                write!(f, "Permission denied for {}", path.display())
+            }
+            CoreError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            CoreError::InvalidValue(msg) => write!(f, "{}", msg),
+            CoreError::Unsupported(msg) => write!(f, "{}", msg),
+            CoreError::Io(path, e) => write!(f, "Failed to access {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
@@ -2,9 +2,21 @@ use log::debug;
 use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
+/// One load sample: average usage (0-100) across online CPUs, overall and per core type.
+/// The per-type averages are 0 when no CPU of that type is online or the topology is
+/// unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadSnapshot {
+    pub avg: f32,
+    pub p_core_avg: f32,
+    pub e_core_avg: f32,
+}
+
 pub struct LoadTracker {
-    history: VecDeque<(f32, Instant)>,
+    history: VecDeque<(f32, Instant)>, // Overall average of each snapshot
+    latest: LoadSnapshot,
     window_size: Duration,
+    max_samples: Option<usize>,
     pub last_change: Instant,
 }
 
@@ -13,15 +25,33 @@ impl LoadTracker {
         Self {
             history: VecDeque::new(),
             window_size,
+            max_samples: None,
+            latest: LoadSnapshot::default(),
             last_change: Instant::now(),
         }
     }
 
-    pub fn add_measurement(&mut self, load: f32) {
+    /// Caps how many samples are kept, dropping the oldest even if still inside the window.
+    pub fn with_max_samples(mut self, max_samples: Option<usize>) -> Self {
+        self.max_samples = max_samples.map(|max| max.max(1));
+        self
+    }
+
+    /// Changes the averaging window; samples that fall outside it go with the next
+    /// measurement.
+    pub fn set_window_size(&mut self, window_size: Duration) {
+        self.window_size = window_size;
+    }
+
+    pub fn add_measurement(&mut self, snapshot: LoadSnapshot) {
         let now = Instant::now();
 
-        self.history.push_back((load, now));
-        debug!("Added load measurement: {:.2}%", load);
+        self.history.push_back((snapshot.avg, now));
+        self.latest = snapshot;
+        debug!(
+            "Added load measurement: {:.2}% (P-cores {:.2}%, E-cores {:.2}%)",
+            snapshot.avg, snapshot.p_core_avg, snapshot.e_core_avg
+        );
 
         let cutoff = now - self.window_size;
         let old_len = self.history.len();
@@ -34,6 +64,12 @@ impl LoadTracker {
             }
         }
 
+        if let Some(max_samples) = self.max_samples {
+            while self.history.len() > max_samples {
+                self.history.pop_front();
+            }
+        }
+
         if old_len != self.history.len() {
             debug!(
                 "Pruned {} old measurements from history",
@@ -48,6 +84,11 @@ impl LoadTracker {
         );
     }
 
+    /// The most recent snapshot, with the per-core-type split.
+    pub fn latest(&self) -> LoadSnapshot {
+        self.latest
+    }
+
     pub fn get_average(&self) -> f32 {
         if self.history.is_empty() {
             return 0.0;
@@ -56,6 +97,98 @@ impl LoadTracker {
         sum / self.history.len() as f32
     }
 
+    /// Average where each sample is weighted linearly by recency: the newest counts fully,
+    /// samples approaching the window edge count close to nothing.
+    pub fn get_weighted_average(&self) -> f32 {
+        let Some((_, newest)) = self.history.back() else {
+            return 0.0;
+        };
+        let window = self.window_size.as_secs_f32().max(f32::EPSILON);
+
+        let (sum, weights) =
+            self.history
+                .iter()
+                .fold((0.0, 0.0), |(sum, weights), (load, time)| {
+                    let age = newest.duration_since(*time).as_secs_f32();
+                    let weight = (1.0 - age / window).max(0.0);
+                    (sum + load * weight, weights + weight)
+                });
+        sum / weights
+    }
+
+    /// Exponential moving average of the samples in the window, oldest first: each sample
+    /// moves the average `alpha` of the way towards itself, so recent load dominates.
+    pub fn get_ewma(&self, alpha: f32) -> f32 {
+        let mut loads = self.history.iter().map(|(load, _)| *load);
+        let Some(first) = loads.next() else {
+            return 0.0;
+        };
+        loads.fold(first, |ewma, load| ewma + alpha * (load - ewma))
+    }
+
+    /// How long load has been continuously above `threshold`, up to the newest sample. As
+    /// in `fraction_above`, each sample covers the time since the previous one.
+    pub fn time_above(&self, threshold: f32) -> Duration {
+        let Some(&(_, newest)) = self.history.back() else {
+            return Duration::ZERO;
+        };
+        let mut since = newest;
+        for (load, time) in self.history.iter().rev() {
+            since = *time;
+            if *load <= threshold {
+                break;
+            }
+        }
+        newest.duration_since(since)
+    }
+
+    /// Time-weighted fraction (0.0-1.0) of the window during which load was above
+    /// `threshold`. Each sample covers the time since the previous one, so the oldest
+    /// sample only counts when it is the only one.
+    pub fn fraction_above(&self, threshold: f32) -> f32 {
+        match self.history.len() {
+            0 => return 0.0,
+            1 => {
+                return if self.history[0].0 > threshold {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            _ => {}
+        }
+
+        let (above, total) = self.history.iter().zip(self.history.iter().skip(1)).fold(
+            (0.0, 0.0),
+            |(above, total), ((_, prev), (load, time))| {
+                let span = time.duration_since(*prev).as_secs_f32();
+                let above = if *load > threshold {
+                    above + span
+                } else {
+                    above
+                };
+                (above, total + span)
+            },
+        );
+        if total > 0.0 {
+            above / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Nearest-rank percentile (0-100) of the samples in the window.
+    pub fn get_percentile(&self, p: f32) -> f32 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+        let mut loads: Vec<f32> = self.history.iter().map(|(load, _)| *load).collect();
+        loads.sort_by(|a, b| a.total_cmp(b));
+
+        let rank = ((p.clamp(0.0, 100.0) / 100.0) * loads.len() as f32).ceil() as usize;
+        loads[rank.saturating_sub(1).min(loads.len() - 1)]
+    }
+
     pub fn record_change(&mut self) {
         let previous = self.last_change;
         self.last_change = Instant::now();
@@ -74,24 +207,133 @@ impl LoadTracker {
 mod tests {
     use super::*;
 
+    fn load(avg: f32) -> LoadSnapshot {
+        LoadSnapshot {
+            avg,
+            ..LoadSnapshot::default()
+        }
+    }
+
     #[test]
     fn test_load_tracker_average() {
         let mut tracker = LoadTracker::new(Duration::from_secs(30));
-        tracker.add_measurement(50.0);
-        tracker.add_measurement(100.0);
+        tracker.add_measurement(load(50.0));
+        tracker.add_measurement(load(100.0));
         assert_eq!(tracker.get_average(), 75.0);
     }
 
+    #[test]
+    fn test_percentile() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        for avg in [10.0, 90.0, 20.0, 90.0, 30.0, 90.0, 40.0, 90.0, 50.0, 90.0] {
+            tracker.add_measurement(load(avg));
+        }
+        assert_eq!(tracker.get_average(), 60.0);
+        assert_eq!(tracker.get_percentile(90.0), 90.0);
+        assert_eq!(tracker.get_percentile(50.0), 50.0);
+        assert_eq!(tracker.get_percentile(0.0), 10.0);
+        assert_eq!(tracker.get_percentile(100.0), 90.0);
+
+        // 1..=100: the p-th percentile is p
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        for avg in (1..=100).rev() {
+            tracker.add_measurement(load(avg as f32));
+        }
+        for p in [1.0, 25.0, 75.0, 99.0] {
+            assert_eq!(tracker.get_percentile(p), p);
+        }
+
+        // Occasional full-load blips lift the mean but not the 75th percentile
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        for i in 0..20 {
+            tracker.add_measurement(load(if i % 5 == 0 { 100.0 } else { 20.0 }));
+        }
+        assert_eq!(tracker.get_average(), 36.0);
+        assert_eq!(tracker.get_percentile(75.0), 20.0);
+    }
+
+    #[test]
+    fn test_weighted_average_favors_recent_samples() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(10));
+        let newest = Instant::now();
+        // Ages 8s, 4s and 0s get weights 0.2, 0.6 and 1.0
+        for (load, age) in [(10.0, 8), (50.0, 4), (90.0, 0)] {
+            tracker
+                .history
+                .push_back((load, newest - Duration::from_secs(age)));
+        }
+        let expected = (10.0 * 0.2 + 50.0 * 0.6 + 90.0 * 1.0) / 1.8;
+        assert!((tracker.get_weighted_average() - expected).abs() < 0.01);
+        assert!(tracker.get_weighted_average() > tracker.get_average());
+    }
+
+    #[test]
+    fn test_ewma_reacts_faster_than_mean() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        for avg in [10.0, 10.0, 10.0, 10.0, 90.0] {
+            tracker.add_measurement(load(avg));
+        }
+        // A spike at the end: the mean damps it as much as the oldest samples
+        assert_eq!(tracker.get_average(), 26.0);
+        assert!((tracker.get_ewma(0.5) - 50.0).abs() < 0.001);
+        // alpha 1.0 is the newest sample alone
+        assert_eq!(tracker.get_ewma(1.0), 90.0);
+
+        let steady = LoadTracker::new(Duration::from_secs(30));
+        assert_eq!(steady.get_ewma(0.5), 0.0);
+    }
+
+    #[test]
+    fn test_fraction_above_is_time_weighted() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        let start = Instant::now();
+        // 90% for 6s, 20% for 2s, 95% for 2s
+        for (load, at) in [(50.0, 0), (90.0, 6), (20.0, 8), (95.0, 10)] {
+            tracker
+                .history
+                .push_back((load, start + Duration::from_secs(at)));
+        }
+        assert!((tracker.fraction_above(80.0) - 0.8).abs() < 0.001);
+        assert_eq!(tracker.fraction_above(99.0), 0.0);
+    }
+
+    #[test]
+    fn test_time_above_counts_the_trailing_run() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(30));
+        assert_eq!(tracker.time_above(80.0), Duration::ZERO);
+        let start = Instant::now();
+        for (load, at) in [(90.0, 0), (20.0, 4), (85.0, 6), (95.0, 9)] {
+            tracker
+                .history
+                .push_back((load, start + Duration::from_secs(at)));
+        }
+        // Above since the 20% sample at 4s
+        assert_eq!(tracker.time_above(80.0), Duration::from_secs(5));
+        assert_eq!(tracker.time_above(90.0), Duration::from_secs(3));
+        assert_eq!(tracker.time_above(99.0), Duration::ZERO);
+        assert_eq!(tracker.time_above(10.0), Duration::from_secs(9));
+    }
+
+    #[test]
+    fn test_max_samples_cap() {
+        let mut tracker = LoadTracker::new(Duration::from_secs(3600)).with_max_samples(Some(3));
+        for avg in [10.0, 20.0, 30.0, 40.0, 50.0] {
+            tracker.add_measurement(load(avg));
+        }
+        assert_eq!(tracker.history.len(), 3);
+        assert_eq!(tracker.get_average(), 40.0);
+    }
+
     #[test]
     fn test_window_pruning() {
         let window = Duration::from_secs(2);
         let mut tracker = LoadTracker::new(window);
 
-        tracker.add_measurement(50.0);
+        tracker.add_measurement(load(50.0));
 
         std::thread::sleep(Duration::from_secs(3));
 
-        tracker.add_measurement(100.0);
+        tracker.add_measurement(load(100.0));
 
         assert_eq!(tracker.history.len(), 1);
         assert_eq!(tracker.get_average(), 100.0);
@@ -1,290 +1,1609 @@
-use crate::config::Settings;
-use crate::system::PowerState;
-use log::{debug, error, info, warn};
-use std::error::Error;
+use crate::config::{
+    EppCoreOrder, LoadSmoothing, MinCores, PercentageRounding, Profile, ScalingMode, Settings,
+    ThrottleMethod,
+};
+use crate::system::capabilities::Capabilities;
+use crate::system::cpufreq::{self, CpufreqDriver};
+use crate::system::idle::{self, IdleSource};
+use crate::system::rapl::{self, PackagePowerMeter};
+use crate::system::sysfs::{LinuxSysfs, SysfsProvider};
+use crate::system::{cgroup, hwmon, power, powerclamp, thermal, PowerState};
+#[cfg(feature = "remote_target")]
+use crate::utils::http;
+use crate::utils::{hooks, logging};
+use log::{debug, error, info, warn, Level};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 #[cfg(target_os = "linux")]
+use std::collections::HashSet;
+use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 #[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sysinfo::System;
 
-use super::load_tracker::LoadTracker;
+use super::epp;
+#[cfg(target_os = "linux")]
+use super::error::CoreError;
+use super::load_tracker::{LoadSnapshot, LoadTracker};
+use super::power_durations::PowerDurations;
+#[cfg(target_os = "linux")]
+use super::session_state::SessionState;
+use super::snapshot::CoreSnapshot;
 #[cfg(target_os = "linux")]
-use super::topology::CPUTopology;
+use super::topology::{
+    enumerate_cpu_ids, filter_present, read_online_mask, sysfs_cpu_index, CPU_SYSFS_PATH,
+};
+use super::topology::{CPUTopology, CoreType, SelectionPolicy};
+
+/// The ends of the core range, for firing the min/max hooks on entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CoreExtreme {
+    Min,
+    Max,
+}
 
 pub struct CoreManager {
     settings: Settings,
-    #[allow(dead_code)] // Temporary until fully implemented
     topology: CPUTopology,
     sys: System,
     current_cores: usize,
     load_tracker: LoadTracker,
     last_power_state: Option<PowerState>,
+    powerclamp_device: Option<PathBuf>,
+    inhibited: bool,
+    idle_source: Option<Box<dyn IdleSource>>,
+    input_idle: bool,
+    cpufreq_driver: CpufreqDriver,
+    cgroup_core_limit: Option<usize>,
+    applied_profile: Option<Profile>,
+    no_room_logged: bool,
+    last_direction: Option<Ordering>, // Direction of the last load-based change
+    hold_released_at: Option<Instant>, // Start of the post-release grace period
+    hotplug_counts: BTreeMap<usize, u64>, // Verified online/offline toggles per CPU
+    power_meter: Option<PackagePowerMeter>, // Only with power_envelope_watts set
+    iterations: u64,                  // Decisions made since startup
+    online_mask_available: bool,      // cpu/online lists all online CPUs in one read
+    power_applied_at: Option<Instant>, // When EPP/governor were last written
+    pending_profile: Option<(Profile, PowerState)>, // Deferred until after core changes (cores_first)
+    at_extreme: Option<CoreExtreme>, // Whether the last change reached min or max cores
+    power_durations: Option<PowerDurations>, // Time per power state, when track_power_durations is set
+    min_cores: usize, // settings.min_cores resolved against the core count at startup
+    smoothed_usage: Vec<f32>, // Per-core usage EWMA, indexed like sys.cpus()
+    capabilities: Capabilities, // Levers found writable at startup; cleanup skips the rest
+    sysfs: Arc<dyn SysfsProvider>, // Topology and CPU online state go through this
+    restore_on_exit: bool, // Drop brings all cores back; off for --once
+    below_down_streak: u32, // Consecutive evaluations with load below the scale-down threshold
+    original_epp: Option<String>, // EPP found at startup, restored on shutdown
+    core_inventory: Vec<usize>, // Cores found at startup; rescanned on refresh_core_inventory
+    core_changes: u64, // Successful core count changes since startup
+    enabled_at: BTreeMap<usize, Instant>, // When observer last onlined each core
+    deferred_disables: bool, // Cores left online by min_core_uptime_sec; retried next decision
+    power_transition_at: Option<Instant>, // Last AC/battery change, for the cooldown
+    usage_primed: bool, // First refresh_usage took its baseline sample
+    battery_percent: Option<f32>, // Charge read this iteration, only while on battery
+    one_shot: bool,   // --once: the single decision skips the interval and confirmation gates
 }
 
 impl CoreManager {
     pub fn new(settings: crate::config::Settings) -> Result<Self, Box<dyn Error>> {
+        Self::build(settings, Arc::new(LinuxSysfs), true)
+    }
+
+    /// Test constructor: topology and CPU online state go through `sysfs`, and the host's
+    /// cpufreq driver, EPP and writable levers are not probed. Without them no profile or
+    /// cleanup reaches the real /sys, and the state is left alone on drop.
+    #[cfg(test)]
+    pub(crate) fn with_sysfs(
+        settings: crate::config::Settings,
+        sysfs: Arc<dyn SysfsProvider>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut manager = Self::build(settings, sysfs, false)?;
+        manager.keep_state_on_exit();
+        Ok(manager)
+    }
+
+    /// Reads the topology and CPU online state through `sysfs`. `probe_hardware` detects the
+    /// cpufreq driver, original EPP and writable levers on the running system.
+    fn build(
+        settings: crate::config::Settings,
+        sysfs: Arc<dyn SysfsProvider>,
+        probe_hardware: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        #[cfg(target_os = "linux")]
+        Self::recover_previous_session(&settings, &*sysfs);
+
         let settings_clone = settings.clone();
         #[cfg(target_os = "linux")]
-        let topology = CPUTopology::new();
+        let topology = {
+            let cpu_path = Path::new(CPU_SYSFS_PATH);
+            let mut topology = CPUTopology::from_provider(&*sysfs, cpu_path);
+            topology.set_protected_cores(&*sysfs, cpu_path, &settings.protected_cores);
+            topology
+        };
         #[cfg(not(target_os = "linux"))]
         let topology = CPUTopology::default();
 
+        let sys = System::new_all();
+
+        #[cfg(target_os = "linux")]
+        let capabilities = if probe_hardware {
+            crate::system::capabilities::probe()
+        } else {
+            Capabilities::default()
+        };
+        #[cfg(not(target_os = "linux"))]
+        let capabilities = Capabilities::default();
+
+        let min_cores = settings.min_cores.resolve(sys.cpus().len());
+        if let MinCores::Percent(_) = settings.min_cores {
+            info!(
+                "Resolved min_cores = {} to {} of {} cores",
+                settings.min_cores,
+                min_cores,
+                sys.cpus().len()
+            );
+        }
+
         let total_cores = topology.num_p_cores + topology.num_e_cores;
-        let initial_cores = topology.cores.len() * 2;
+        #[cfg(target_os = "linux")]
+        let initial_cores = match Self::count_online_cores(&*sysfs, Path::new(CPU_SYSFS_PATH)) {
+            0 => sys.cpus().len(),
+            n => n,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let initial_cores = sys.cpus().len();
         info!(
             "Initializing CoreManager. Found {} physical cores, {} logical cores initially online.",
             total_cores, initial_cores
         );
+
+        #[cfg(target_os = "linux")]
+        let core_inventory =
+            Self::available_cores_in(&*sysfs, Path::new(CPU_SYSFS_PATH)).unwrap_or_default();
+        #[cfg(not(target_os = "linux"))]
+        let core_inventory = Vec::new();
+
+        #[cfg(target_os = "linux")]
+        let online_mask_available = read_online_mask(&*sysfs, Path::new(CPU_SYSFS_PATH)).is_some();
+        #[cfg(not(target_os = "linux"))]
+        let online_mask_available = false;
+        debug!(
+            "Online mask {}",
+            if online_mask_available {
+                "available, reading CPU states in one pass"
+            } else {
+                "unavailable, reading CPU states per core"
+            }
+        );
+
+        let powerclamp_device = match settings.throttle_method {
+            ThrottleMethod::Offline => None,
+            ThrottleMethod::IdleInject => {
+                let device = powerclamp::find_device("/sys/class/thermal");
+                match &device {
+                    Some(path) => info!("Using idle injection via {}", path.display()),
+                    None => warn!(
+                        "Idle injection requested but intel_powerclamp is unavailable. Falling back to offlining cores."
+                    ),
+                }
+                device
+            }
+        };
+
+        let idle_source = match settings.input_idle_sec {
+            Some(_) => {
+                let mut source = idle::source_for(settings.input_idle_source);
+                if source.idle_duration().is_some() {
+                    info!("Input idle detection enabled via {}", source.name());
+                    Some(source)
+                } else {
+                    warn!(
+                        "Input idle source '{}' is unavailable. Input idle mode disabled.",
+                        source.name()
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+
+        let cpufreq_driver = if probe_hardware {
+            cpufreq::detect_driver()
+        } else {
+            CpufreqDriver::None
+        };
+        info!(
+            "cpufreq driver: {:?} ({})",
+            cpufreq_driver,
+            if cpufreq_driver.supports_epp() {
+                "using EPP hints"
+            } else {
+                "using governors"
+            }
+        );
+
+        // Read before any profile is applied, so shutdown can put it back
+        let original_epp = if cpufreq_driver.supports_epp() {
+            epp::list_epp_policies()
+                .first()
+                .and_then(|&policy| epp::read_epp_for_policy(policy).ok())
+        } else {
+            None
+        };
+
+        let cgroup_core_limit = if settings.respect_cgroup_quota {
+            match cgroup::effective_cpu_quota() {
+                Some(quota) => {
+                    let limit = (quota.ceil() as usize).max(1);
+                    info!(
+                        "cgroup CPU quota is {:.2} cores; scaling against {} cores",
+                        quota, limit
+                    );
+                    Some(limit)
+                }
+                None => {
+                    info!("No cgroup CPU quota found; scaling against all cores");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let power_meter = settings.power_envelope_watts.map(|envelope| {
+            let zone = Path::new(rapl::RAPL_PACKAGE_PATH);
+            if zone.exists() {
+                info!("Keeping package power under {:.1} W", envelope);
+            } else {
+                warn!(
+                    "power_envelope_watts is set but {} is missing. Envelope ignored.",
+                    zone.display()
+                );
+            }
+            PackagePowerMeter::new(zone)
+        });
+
+        if topology.cores.is_empty() {
+            warn!(
+                "CPU topology unavailable: P/E, cache and NUMA aware selection is disabled; cores are picked in index order."
+            );
+            if let Some(percentage) = settings.no_topology_core_percentage {
+                info!("Using no_topology_core_percentage = {}%", percentage);
+            }
+        }
+
+        if let Some(advice) = battery_core_advisory(&settings, &topology) {
+            warn!("{}", advice);
+        }
+
+        let power_durations = settings
+            .track_power_durations
+            .then(|| PowerDurations::new(settings.power_durations_by_core_count));
+
         Ok(Self {
             settings: settings_clone.clone(),
             topology,
-            sys: System::new_all(),
+            sys,
             current_cores: initial_cores,
-            load_tracker: LoadTracker::new(Duration::from_secs(settings_clone.load_window_sec)),
+            load_tracker: LoadTracker::new(Duration::from_secs(settings_clone.load_window_sec))
+                .with_max_samples(settings_clone.max_history_samples),
             last_power_state: None,
+            powerclamp_device,
+            inhibited: false,
+            idle_source,
+            input_idle: false,
+            cpufreq_driver,
+            cgroup_core_limit,
+            applied_profile: None,
+            no_room_logged: false,
+            last_direction: None,
+            hold_released_at: None,
+            hotplug_counts: BTreeMap::new(),
+            power_meter,
+            iterations: 0,
+            online_mask_available,
+            power_applied_at: None,
+            pending_profile: None,
+            at_extreme: None,
+            power_durations,
+            min_cores,
+            smoothed_usage: Vec::new(),
+            capabilities,
+            sysfs,
+            restore_on_exit: true,
+            below_down_streak: 0,
+            original_epp,
+            core_inventory,
+            core_changes: 0,
+            enabled_at: BTreeMap::new(),
+            deferred_disables: false,
+            power_transition_at: None,
+            usage_primed: false,
+            battery_percent: None,
+            one_shot: false,
         })
     }
 
     #[cfg(target_os = "linux")]
-    fn calculate_current_load(&self) -> f32 {
-        let active_cpus: Vec<_> = self
+    fn calculate_current_load(&self) -> LoadSnapshot {
+        // Match entries by name rather than list position, which shifts when sysinfo
+        // leaves offline CPUs out
+        let online_mask = if self.online_mask_available {
+            read_online_mask(&*self.sysfs, Path::new(CPU_SYSFS_PATH))
+        } else {
+            None
+        };
+        let active_cpus: Vec<(usize, f32)> = self
             .sys
             .cpus()
             .iter()
             .enumerate()
+            .map(|(position, cpu)| (sysfs_cpu_index(cpu.name(), position), cpu.cpu_usage()))
             .filter(|(i, _)| {
-                if *i == 0 {
-                    return true; // CPU0 always active
+                if self.topology.protected_cores.contains(i) {
+                    return true; // Protected CPUs are never taken offline
+                }
+                if let Some(mask) = &online_mask {
+                    return mask.contains(i);
                 }
-                let cpu_path = format!("/sys/devices/system/cpu/cpu{}/online", i);
-                match fs::read_to_string(&cpu_path) {
+                let cpu_path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, i);
+                match self.sysfs.read(Path::new(&cpu_path)) {
                     Ok(content) => content.trim() == "1",
                     Err(_) => false, // Assume offline if cannot read state
                 }
             })
             .collect();
 
-        let active_count = active_cpus.len().max(1); // Avoid division by zero
-
-        let total_load: f32 = active_cpus
-            .iter()
-            .map(|(_, cpu)| cpu.cpu_usage())
-            .sum::<f32>();
-
-        let avg_load = total_load / active_count as f32;
+        let load = summarize_load(&self.topology, &active_cpus);
         debug!(
-            "Linux Load calc: total={:.2}% across {} active cores, avg={:.2}%",
-            total_load, active_count, avg_load
+            "Linux Load calc: avg={:.2}% across {} active cores (P-cores {:.2}%, E-cores {:.2}%)",
+            load.avg,
+            active_cpus.len(),
+            load.p_core_avg,
+            load.e_core_avg
         );
-        total_load // Return total load as before
+        load
     }
 
     #[cfg(not(target_os = "linux"))]
-    fn calculate_current_load(&self) -> f32 {
-        let cpus = self.sys.cpus();
-        let count = cpus.len().max(1);
-        let total_load: f32 = cpus.iter().map(|cpu| cpu.cpu_usage()).sum();
-        let avg_load = total_load / count as f32;
+    fn calculate_current_load(&self) -> LoadSnapshot {
+        let cpus: Vec<(usize, f32)> = self
+            .sys
+            .cpus()
+            .iter()
+            .map(|cpu| cpu.cpu_usage())
+            .enumerate()
+            .collect();
+        let load = summarize_load(&self.topology, &cpus);
         debug!(
-            "Non-Linux Load calc: total={:.2}% across {} logical cores, avg={:.2}%",
-            total_load, count, avg_load
+            "Non-Linux Load calc: avg={:.2}% across {} logical cores",
+            load.avg,
+            cpus.len()
         );
-        total_load // Return total load
+        load
     }
 
     #[cfg(target_os = "linux")]
     pub fn get_available_cores() -> Result<Vec<usize>, Box<dyn Error>> {
-        let mut cores = Vec::new();
-        let cpu_path = Path::new("/sys/devices/system/cpu");
-
-        for i in 0..256 {
-            let core_path = cpu_path.join(format!("cpu{}", i));
-            if core_path.exists() {
-                if i == 0 || core_path.join("online").exists() {
-                    cores.push(i);
-                } else {
-                    debug!(
-                        "Core {} directory exists but 'online' file missing, not adding.",
-                        i
-                    );
-                }
-            } else if i > 0 {
-                // Don't break immediately if cpu0 is missing for some reason
-                break;
-            }
-        }
+        Self::available_cores_in(&LinuxSysfs, Path::new(CPU_SYSFS_PATH))
+    }
 
+    #[cfg(target_os = "linux")]
+    fn available_cores_in(
+        sysfs: &dyn SysfsProvider,
+        cpu_path: &Path,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        // CPUs without an 'online' file are listed too; the topology marks them protected
+        let cores = filter_present(sysfs, cpu_path, enumerate_cpu_ids(sysfs, cpu_path));
         if cores.is_empty() {
-            Err("No CPU cores found in /sys/devices/system/cpu".into())
+            Err(format!("No CPU cores found in {}", cpu_path.display()).into())
         } else {
             Ok(cores)
         }
     }
 
+    /// Counts the logical CPUs currently online. CPUs without an `online` file can't be
+    /// offlined and are counted as online.
+    #[cfg(target_os = "linux")]
+    fn count_online_cores(sysfs: &dyn SysfsProvider, cpu_path: &Path) -> usize {
+        filter_present(sysfs, cpu_path, enumerate_cpu_ids(sysfs, cpu_path))
+            .into_iter()
+            .filter(
+                |i| match sysfs.read(&cpu_path.join(format!("cpu{}/online", i))) {
+                    Ok(content) => content.trim() == "1",
+                    Err(_) => true,
+                },
+            )
+            .count()
+    }
+
     #[cfg(not(target_os = "linux"))]
     pub fn get_available_cores() -> Result<Vec<usize>, Box<dyn Error>> {
         warn!("Core enumeration through /sysfs is only supported on Linux. Reporting core 0 only.");
         Ok(vec![0]) // Return core 0 as a default/fallback
     }
 
-    pub fn get_optimal_core_count(&mut self, on_battery: bool) -> Result<usize, Box<dyn Error>> {
+    /// Re-reads the list of cores from sysfs, e.g. after CPUs were hot-added or removed.
+    #[cfg(target_os = "linux")]
+    pub fn refresh_core_inventory(&mut self) {
+        match Self::available_cores_in(&*self.sysfs, Path::new(CPU_SYSFS_PATH)) {
+            Ok(cores) => {
+                if cores != self.core_inventory {
+                    info!("Core inventory changed: {:?}", cores);
+                }
+                self.core_inventory = cores;
+            }
+            Err(e) => warn!("Failed to refresh the core inventory: {}", e),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn refresh_core_inventory(&mut self) {}
+
+    /// The cached core list. With cpu_hotplug_aware set, it is read again once a cached core
+    /// has disappeared.
+    #[cfg(target_os = "linux")]
+    fn available_cores(&mut self) -> Result<Vec<usize>, Box<dyn Error>> {
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        let core_vanished = self.settings.cpu_hotplug_aware
+            && self
+                .core_inventory
+                .iter()
+                .any(|core| !self.sysfs.exists(&cpu_path.join(format!("cpu{}", core))));
+        if core_vanished || self.core_inventory.is_empty() {
+            self.core_inventory = Self::available_cores_in(&*self.sysfs, cpu_path)?;
+        }
+        Ok(self.core_inventory.clone())
+    }
+
+    /// Refreshes CPU usage and folds each core's sample into its moving average.
+    fn refresh_usage(&mut self) {
+        // sysinfo reports usage as the delta since the previous refresh, which the first
+        // reading doesn't have yet; take a short baseline sample so it isn't 0% or stale
+        if !self.usage_primed {
+            self.usage_primed = true;
+            if self.settings.cpu_sample_interval_ms > 0 {
+                self.sys.refresh_cpu_all();
+                thread::sleep(Duration::from_millis(self.settings.cpu_sample_interval_ms));
+            }
+        }
         self.sys.refresh_cpu_all();
+        let samples: Vec<f32> = self.sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect();
+        ewma_update(
+            &mut self.smoothed_usage,
+            &samples,
+            self.settings.per_core_smoothing_alpha,
+        );
+    }
+
+    /// Leaves cores, EPP and governor as they are when the manager is dropped, for one-shot
+    /// runs whose changes should outlive the process.
+    pub fn keep_state_on_exit(&mut self) {
+        self.restore_on_exit = false;
+    }
+
+    /// Makes the next decision final for `--once`: it applies straight away, without the
+    /// change-interval, reversal, startup and confirmation gates meant to damp a running
+    /// loop, and is left in place on exit.
+    pub fn one_shot(&mut self) {
+        self.one_shot = true;
+        self.keep_state_on_exit();
+    }
+
+    /// Samples load without deciding anything, keeping the history warm while paused.
+    pub fn observe(&mut self) {
+        self.refresh_usage();
+        let current_load = self.calculate_current_load();
+        self.load_tracker.add_measurement(current_load);
+    }
+
+    pub fn get_optimal_core_count(&mut self, on_battery: bool) -> Result<usize, Box<dyn Error>> {
+        self.refresh_usage();
+        self.iterations += 1;
+        // Read once per iteration; profile selection and the low-battery limit share it
+        self.battery_percent = on_battery
+            .then(|| power::get_battery_percentage(&self.settings.power_supply_path))
+            .flatten();
 
         let current_load = self.calculate_current_load();
         self.load_tracker.add_measurement(current_load);
 
-        let time_since_last_change = self.load_tracker.time_since_last_change();
+        if let Some(forced) = self.settings.force_core_count {
+            let target = forced.clamp(1, self.total_cores().max(1));
+            if target != self.current_cores {
+                self.load_tracker.record_change();
+                info!("Forcing {} cores (force_core_count = {})", target, forced);
+            }
+            self.update_power_state(on_battery);
+            return Ok(target);
+        }
+
+        if self.update_inhibit() {
+            let total_cores = self.total_cores();
+            if total_cores != self.current_cores {
+                self.load_tracker.record_change();
+            }
+            self.update_power_state(false);
+            return Ok(total_cores);
+        }
+
+        let was_input_idle = self.input_idle;
+        if self.update_input_idle() || was_input_idle {
+            // Idle parks deeply; returning input restores the power-state limit right away
+            let core_percentage = if self.input_idle {
+                self.settings.input_idle_core_percentage
+            } else {
+                self.core_percentage(on_battery)
+            };
+            let target = percentage_limit(
+                self.total_cores(),
+                core_percentage,
+                self.min_cores,
+                self.settings.percentage_rounding,
+            );
+            if target != self.current_cores {
+                self.load_tracker.record_change();
+            }
+            self.update_power_state(on_battery);
+            return Ok(target);
+        }
 
-        if time_since_last_change < Duration::from_secs(self.settings.min_change_interval_sec) {
-            debug!("Skipping core adjustment - min interval not reached");
+        // Unplugging AC comes with a burst of desktop activity; let it settle before scaling.
+        // EPP and governor still follow the new power state right away.
+        if self.in_power_transition_cooldown(on_battery) {
+            debug!(
+                "Holding {} cores - power state changed less than {}s ago",
+                self.current_cores,
+                self.settings.power_transition_cooldown_sec.unwrap_or(0)
+            );
+            self.update_power_state(on_battery);
             return Ok(self.current_cores);
         }
 
-        let avg_load = self.load_tracker.get_average();
-        let total_cores = self.sys.cpus().len();
-        let min_cores = self.settings.min_cores;
+        let avg_load = self.average_load();
+        let total_cores = self.total_cores();
+        let min_cores = self.min_cores;
 
-        let load_threshold = if on_battery {
-            self.settings.cpu_load_threshold
-        } else {
-            self.settings.ac_cpu_load_threshold
-        };
+        let profile = self.active_profile(on_battery);
+        let load_threshold = profile.load_threshold;
+        let mut core_percentage = self.core_percentage(on_battery);
+        if let Some(low_battery_percentage) = self.settings.low_battery_core_percentage {
+            let charge = self.battery_percent.filter(|_| on_battery);
+            if charge.is_some_and(|charge| charge < self.settings.low_battery_percentage) {
+                debug!(
+                    "Battery below {:.0}%, limiting cores to {}%",
+                    self.settings.low_battery_percentage, low_battery_percentage
+                );
+                core_percentage = core_percentage.min(low_battery_percentage);
+            }
+        }
+        let percentage_limit = percentage_limit(
+            total_cores,
+            core_percentage,
+            min_cores,
+            self.settings.percentage_rounding,
+        );
 
-        let core_percentage = if on_battery {
-            self.settings.battery_core_percentage
+        let step_policy = StepPolicy::new(&self.settings, load_threshold);
+        if avg_load < step_policy.down_threshold() {
+            self.below_down_streak = self.below_down_streak.saturating_add(1);
         } else {
-            self.settings.ac_core_percentage
-        };
-        let percentage_limit = (total_cores as f32 * (core_percentage as f32 / 100.0))
-            .ceil()
-            .max(min_cores as f32) as usize;
-
-        let target_cores = if avg_load > load_threshold * 1.2 && self.current_cores < total_cores {
-            (self.current_cores + 2).min(total_cores)
-        } else if avg_load < load_threshold * 0.8 && self.current_cores > min_cores {
-            (self.current_cores.saturating_sub(2))
-                .max(min_cores)
-                .min(percentage_limit)
-        } else {
-            self.current_cores
-        };
+            self.below_down_streak = 0;
+        }
 
-        let optimal_cores = target_cores;
+        // Bursty loads can sit below the threshold on average, so ramp-up may use a percentile
+        let mut rampup_load = match self.settings.rampup_percentile {
+            Some(p) => self.load_tracker.get_percentile(p),
+            None => avg_load,
+        };
+        // Spikes alone don't count as demand unless they fill enough of the window
+        if let Some(duty_cycle) = self.settings.rampup_duty_cycle {
+            let fraction = self.load_tracker.fraction_above(load_threshold);
+            if fraction < duty_cycle {
+                debug!(
+                    "Load above {:.0}% for {:.0}% of the window, below the {:.0}% duty cycle",
+                    load_threshold,
+                    fraction * 100.0,
+                    duty_cycle * 100.0
+                );
+                rampup_load = 0.0;
+            }
+        }
+        // Likewise a burst must last rampup_sustain_sec before it adds cores
+        if let Some(sustain) = self.settings.rampup_sustain_sec {
+            let above = self.load_tracker.time_above(step_policy.up_threshold());
+            if above < Duration::from_secs(sustain) {
+                debug!(
+                    "Load above the ramp-up threshold for {:.1}s of the required {}s",
+                    above.as_secs_f32(),
+                    sustain
+                );
+                rampup_load = 0.0;
+            }
+        }
 
-        if optimal_cores != self.current_cores {
-            self.load_tracker.record_change();
+        if total_cores <= min_cores && !self.no_room_logged {
             info!(
-                "Targeting {} cores (current: {}, limit: {}, load: {:.1}%, on_battery: {})",
-                optimal_cores, self.current_cores, percentage_limit, avg_load, on_battery
+                "Only {} cores available with min_cores = {}, holding all cores online",
+                total_cores, min_cores
             );
+            self.no_room_logged = true;
         }
 
-        let current_power_state = if on_battery {
-            PowerState::Battery
+        let remote_target = self.poll_remote_target();
+        let mut optimal_cores = if let Some(remote) = remote_target {
+            debug!("Remote controller asks for {} cores", remote);
+            remote.clamp(min_cores.min(total_cores), total_cores)
+        } else if self.settings.load_core_table.is_empty() {
+            let target = compute_target_cores(
+                self.current_cores,
+                total_cores,
+                min_cores,
+                percentage_limit,
+                avg_load,
+                rampup_load,
+                step_policy,
+            );
+            if self.one_shot {
+                target
+            } else {
+                let confirmed = confirm_rampdown(
+                    self.current_cores,
+                    target,
+                    self.last_direction,
+                    self.below_down_streak,
+                );
+                if confirmed != target {
+                    debug!(
+                    "Holding {} cores - ramp-down right after a ramp-up needs {} low-load evaluations in a row",
+                    self.current_cores, SCALE_DOWN_CONFIRMATIONS
+                );
+                }
+                confirmed
+            }
         } else {
-            PowerState::AC
+            table_target_cores(
+                &self.settings.load_core_table,
+                avg_load,
+                total_cores,
+                min_cores,
+                percentage_limit,
+            )
         };
-        if self.last_power_state != Some(current_power_state) {
-            let epp_hint = match current_power_state {
-                PowerState::AC => &self.settings.ac_epp,
-                PowerState::Battery => &self.settings.battery_epp,
-                PowerState::Unknown => "balance_performance",
-            };
-            info!(
-                "Power state changed to {:?}. Setting EPP hint to '{}'",
-                current_power_state, epp_hint
-            );
-            if let Err(e) = set_epp_hint(epp_hint) {
-                error!("Failed to set EPP hint: {}", e);
+
+        // Package power over the envelope overrides load: step down and never ramp up
+        let package_watts = self
+            .power_meter
+            .as_mut()
+            .and_then(|meter| meter.read_package_power());
+        if let (Some(watts), Some(envelope)) = (package_watts, self.settings.power_envelope_watts) {
+            let capped = self
+                .current_cores
+                .saturating_sub(self.settings.core_step.max(1))
+                .max(min_cores);
+            if watts > envelope && optimal_cores > capped {
+                info!(
+                    "Package power {:.1} W exceeds the {:.1} W envelope, limiting to {} cores",
+                    watts, envelope, capped
+                );
+                optimal_cores = capped;
             }
-            self.last_power_state = Some(current_power_state);
         }
 
-        Ok(optimal_cores)
-    }
-
-    #[cfg(target_os = "linux")]
-    fn perform_core_state_changes(&mut self, target_cores: usize) -> Result<(), Box<dyn Error>> {
-        let available_cores = Self::get_available_cores()?;
-        let mut operation_successful = true;
-        let mut last_error: Option<Box<dyn Error>> = None;
-
-        for core_num in available_cores.iter().skip(1) {
-            let should_enable = core_num < &target_cores;
-            let cpu_state_path = format!("/sys/devices/system/cpu/cpu{}/online", core_num);
-
-            let current_state_result = fs::read_to_string(&cpu_state_path);
-            let currently_enabled = match current_state_result {
-                Ok(content) => content.trim() == "1",
-                Err(e) => {
-                    error!(
-                        "Linux: Failed to read current state for core {}: {}. Skipping change.",
-                        core_num, e
+        if let Some(max_rpm) = self.settings.max_fan_rpm {
+            if let Some(rpm) = hwmon::read_max_fan_rpm().filter(|&rpm| rpm > max_rpm) {
+                let cap = self::percentage_limit(
+                    total_cores,
+                    self.settings.acoustic_core_percentage,
+                    min_cores,
+                    self.settings.percentage_rounding,
+                );
+                if optimal_cores > cap {
+                    info!(
+                        "Fan at {} RPM exceeds {} RPM, limiting to {} cores",
+                        rpm, max_rpm, cap
                     );
-                    operation_successful = false;
-                    last_error = Some(e.into());
-                    continue;
+                    optimal_cores = cap;
                 }
-            };
+            }
+        }
 
-            if should_enable == currently_enabled {
-                continue;
+        if let Some(limit) = self.settings.thermal_limit_celsius {
+            let hottest = thermal::get_max_temp(Path::new(hwmon::HWMON_PATH));
+            if let Some(celsius) = hottest.filter(|&celsius| celsius > limit) {
+                let capped = self
+                    .current_cores
+                    .saturating_sub(self.settings.thermal_core_reduction as usize)
+                    .max(min_cores);
+                if optimal_cores > capped {
+                    info!(
+                        "Thermal limiting active: {:.1}°C exceeds {:.1}°C, limiting to {} cores",
+                        celsius, limit, capped
+                    );
+                    optimal_cores = capped;
+                }
             }
+        }
 
+        optimal_cores = clamp_change(
+            self.current_cores,
+            optimal_cores,
+            self.settings.max_rampdown_per_decision,
+            self.settings.max_rampup_per_decision,
+        );
+
+        if optimal_cores < self.current_cores
+            && !self.one_shot
+            && self.iterations <= u64::from(self.settings.skip_initial_rampdowns)
+        {
             debug!(
-                "Linux: Attempting to {} core {}",
-                if should_enable { "enable" } else { "disable" },
-                core_num
+                "Skipping ramp-down to {} on startup iteration {}",
+                optimal_cores, self.iterations
             );
-            if let Err(e) = fs::write(&cpu_state_path, if should_enable { "1" } else { "0" }) {
-                error!(
-                    "Linux: Failed to {} core {}: {}",
-                    if should_enable { "enable" } else { "disable" },
-                    core_num,
-                    e
-                );
-                operation_successful = false;
-                last_error = Some(e.into());
-            } else {
+            self.update_power_state(on_battery);
+            return Ok(self.current_cores);
+        }
+
+        if optimal_cores < self.current_cores && !self.one_shot && self.in_release_grace() {
+            debug!(
+                "Holding {} cores - post-release grace ramp in effect",
+                self.current_cores
+            );
+            self.update_power_state(on_battery);
+            return Ok(self.current_cores);
+        }
+
+        let min_interval = if optimal_cores > self.current_cores {
+            self.settings
+                .min_rampup_interval_sec
+                .unwrap_or(self.settings.min_change_interval_sec)
+        } else {
+            self.settings
+                .min_rampdown_interval_sec
+                .unwrap_or(self.settings.min_change_interval_sec)
+        };
+        if optimal_cores != self.current_cores
+            && !self.one_shot
+            && self.load_tracker.time_since_last_change() < Duration::from_secs(min_interval)
+        {
+            debug!(
+                "Skipping core adjustment to {} - min {} interval ({}s) not reached",
+                optimal_cores,
+                if optimal_cores > self.current_cores {
+                    "ramp-up"
+                } else {
+                    "ramp-down"
+                },
+                min_interval
+            );
+            self.update_power_state(on_battery);
+            return Ok(self.current_cores);
+        }
+
+        let direction = optimal_cores.cmp(&self.current_cores);
+        if let (Some(lockout), Some(last)) =
+            (self.settings.reversal_lockout_sec, self.last_direction)
+        {
+            if direction == last.reverse()
+                && direction != Ordering::Equal
+                && self.load_tracker.time_since_last_change() < Duration::from_secs(lockout)
+            {
                 debug!(
-                    "Linux: Core {} successfully {}",
-                    core_num,
-                    if should_enable { "enabled" } else { "disabled" }
+                    "Holding {} cores - reversal to {} within {}s lockout",
+                    self.current_cores, optimal_cores, lockout
                 );
-                if should_enable {
-                    thread::sleep(Duration::from_millis(self.settings.transition_delay_ms));
-                }
+                self.update_power_state(on_battery);
+                return Ok(self.current_cores);
             }
         }
 
-        if operation_successful {
-            Ok(())
-        } else {
-            Err(last_error.unwrap_or_else(|| "Unknown error during Linux core management".into()))
+        if optimal_cores != self.current_cores {
+            self.load_tracker.record_change();
+            self.last_direction = Some(direction);
+            let (p_cores, e_cores) = self.target_breakdown(optimal_cores);
+            let breakdown = if self.topology.num_e_cores > 0 {
+                format!(": {} P-cores, {} E-cores", p_cores, e_cores)
+            } else {
+                String::new()
+            };
+            logging::log_event(
+                Level::Info,
+                &format!(
+                    "Targeting {} cores{} (current: {}, limit: {}, load: {:.1}%, on_battery: {})",
+                    optimal_cores,
+                    breakdown,
+                    self.current_cores,
+                    percentage_limit,
+                    avg_load,
+                    on_battery
+                ),
+                &[
+                    ("CORE_COUNT", optimal_cores.to_string()),
+                    ("PREVIOUS_CORE_COUNT", self.current_cores.to_string()),
+                    ("P_CORES", p_cores.to_string()),
+                    ("E_CORES", e_cores.to_string()),
+                    ("AVG_LOAD", format!("{:.1}", avg_load)),
+                ],
+            );
         }
+
+        self.update_power_state(on_battery);
+
+        Ok(optimal_cores)
     }
 
-    #[cfg(not(target_os = "linux"))]
-    fn perform_core_state_changes(&self, target_cores: usize) -> Result<(), Box<dyn Error>> {
-        warn!(
-            "Core enable/disable is only supported on Linux. Requested {} cores.",
-            target_cores
-        );
-        Ok(())
+    /// P/E-core counts in the set selected for `target_cores`.
+    fn target_breakdown(&self, target_cores: usize) -> (usize, usize) {
+        let selected = self
+            .topology
+            .get_cores_to_enable(target_cores, &self.selection_policy());
+        let (p_cores, e_cores, _) = self.topology.type_breakdown(&selected);
+        (p_cores, e_cores)
     }
 
-    pub fn manage_cpu_cores(&mut self, target_cores: usize) -> Result<(), Box<dyn Error>> {
-        if target_cores == self.current_cores {
-            return Ok(());
+    pub fn snapshot(&self) -> CoreSnapshot {
+        let (p_cores, e_cores) = self.target_breakdown(self.current_cores);
+        let (power_state_seconds, power_state_core_seconds) = self
+            .power_durations
+            .as_ref()
+            .map(PowerDurations::totals)
+            .unwrap_or_default();
+        CoreSnapshot {
+            current_cores: self.current_cores,
+            p_cores,
+            e_cores,
+            avg_load: self.average_load(),
+            p_core_load: self.load_tracker.latest().p_core_avg,
+            e_core_load: self.load_tracker.latest().e_core_avg,
+            on_battery: self.last_power_state == Some(PowerState::Battery),
+            power_state: self.last_power_state.map(|state| format!("{:?}", state)),
+            epp: self
+                .applied_profile
+                .as_ref()
+                .map(|profile| profile.epp.clone()),
+            per_core_usage: self.sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            smoothed_core_usage: self.smoothed_usage.clone(),
+            core_changes: self.core_changes,
+            hotplug_counts: self.hotplug_counts.clone(),
+            power_state_seconds,
+            power_state_core_seconds,
         }
+    }
+
+    /// Records the PID, a heartbeat, the parked cores and a restore command in
+    /// deadman_path, so a watchdog can bring cores back if observer dies without running
+    /// its cleanup (SIGKILL, OOM kill). Called every iteration; removed on clean shutdown.
+    #[cfg(target_os = "linux")]
+    pub fn write_deadman_info(&self) -> std::io::Result<()> {
+        let Some(path) = &self.settings.deadman_path else {
+            return Ok(());
+        };
+        let offline = self.offline_cores();
+        let heartbeat = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let info = serde_json::json!({
+            "pid": std::process::id(),
+            "heartbeat": heartbeat,
+            "check_interval_sec": self.settings.check_interval_sec,
+            "current_cores": self.current_cores,
+            "offline_cores": offline,
+            "restore": format!(
+                "for f in {}/cpu[0-9]*/online; do echo 1 > \"$f\"; done",
+                CPU_SYSFS_PATH
+            ),
+        });
+        let tmp_path = Path::new(path).with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&info)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn write_deadman_info(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Managed cores that are currently offline.
+    #[cfg(target_os = "linux")]
+    fn offline_cores(&self) -> Vec<usize> {
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        self.core_inventory
+            .iter()
+            .copied()
+            .filter(|core| !self.topology.protected_cores.contains(core))
+            .filter(|core| {
+                self.sysfs
+                    .read(&cpu_path.join(format!("cpu{}/online", core)))
+                    .is_ok_and(|content| content.trim() == "0")
+            })
+            .collect()
+    }
+
+    /// Records the cores taken offline and the original EPP in state_file, for the next
+    /// start to undo if this process dies without cleaning up.
+    #[cfg(target_os = "linux")]
+    fn write_session_state(&self) {
+        let Some(path) = &self.settings.state_file else {
+            return;
+        };
+        if self.settings.dry_run {
+            return;
+        }
+        let state = SessionState {
+            pid: std::process::id(),
+            offline_cores: self.offline_cores(),
+            original_epp: self.original_epp.clone(),
+        };
+        if let Err(e) = state.write_to(Path::new(path)) {
+            logging::log_deduplicated(
+                Level::Warn,
+                &format!("Failed to write state file {}: {}", path, e),
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn write_session_state(&self) {}
+
+    /// Undoes what a previous session left behind when it died without cleaning up: brings
+    /// its offlined cores back, restores the EPP it found and removes its state file.
+    #[cfg(target_os = "linux")]
+    fn recover_previous_session(settings: &Settings, sysfs: &dyn SysfsProvider) {
+        let Some(path) = settings.state_file.as_deref().map(Path::new) else {
+            return;
+        };
+        let state = match SessionState::load(path) {
+            Ok(Some(state)) => state,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Ignoring unreadable state file {}: {}", path.display(), e);
+                let _ = fs::remove_file(path);
+                return;
+            }
+        };
+        warn!(
+            "Previous session (pid {}) did not clean up; restoring cores {:?} and EPP {:?}",
+            state.pid, state.offline_cores, state.original_epp
+        );
+        if settings.dry_run {
+            return;
+        }
+        for core in &state.offline_cores {
+            let online_path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, core);
+            if let Err(e) = sysfs.write(Path::new(&online_path), "1") {
+                warn!("Failed to bring core {} back online: {}", core, e);
+            }
+        }
+        if let Some(hint) = &state.original_epp {
+            if let Err(e) = set_epp_hint(hint, false) {
+                warn!("Failed to restore EPP '{}': {}", hint, e);
+            }
+        }
+        if let Err(e) = fs::remove_file(path) {
+            warn!("Failed to remove state file {}: {}", path.display(), e);
+        }
+    }
+
+    /// Fetches the core count requested at target_cores_url. None when unset or when the
+    /// endpoint fails, in which case the load-based decision runs instead.
+    #[cfg(feature = "remote_target")]
+    fn poll_remote_target(&self) -> Option<usize> {
+        let url = self.settings.target_cores_url.as_deref()?;
+        let result = http::parse_url(url).and_then(|endpoint| {
+            let body = http::get(&endpoint, REMOTE_TARGET_TIMEOUT).map_err(|e| e.to_string())?;
+            parse_remote_target(&body)
+        });
+        match result {
+            Ok(target) => Some(target),
+            Err(e) => {
+                logging::log_deduplicated(
+                    Level::Warn,
+                    &format!(
+                        "target_cores_url unavailable ({}), falling back to load-based decisions",
+                        e
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "remote_target"))]
+    fn poll_remote_target(&self) -> Option<usize> {
+        None
+    }
+
+    /// Swaps in reloaded settings. Knobs read each iteration (thresholds, percentages,
+    /// profiles, gates) take effect immediately and the power profile is reapplied right
+    /// away; ones consumed at startup, such as throttle_method or the topology
+    /// grouping, keep their startup values until a restart.
+    pub fn update_settings(&mut self, settings: Settings) {
+        let changed = self.settings.changed_keys(&settings);
+        if changed.is_empty() {
+            debug!("Reloaded configuration has no changes");
+            return;
+        }
+        info!("Configuration changed: {}", changed.join(", "));
+        self.min_cores = settings.min_cores.resolve(self.sys.cpus().len());
+        if settings.load_window_sec != self.settings.load_window_sec {
+            self.load_tracker
+                .set_window_size(Duration::from_secs(settings.load_window_sec));
+        }
+        #[cfg(target_os = "linux")]
+        if settings.protected_cores != self.settings.protected_cores {
+            self.topology.set_protected_cores(
+                &*self.sysfs,
+                Path::new(CPU_SYSFS_PATH),
+                &settings.protected_cores,
+            );
+        }
+        self.settings = settings;
+
+        // Re-apply EPP/governor with the new values now rather than at the next power change
+        self.applied_profile = None;
+        self.pending_profile = None;
+        if let Some(power_state) = self.last_power_state {
+            let profile = self.active_profile(power_state == PowerState::Battery);
+            self.apply_profile(profile, power_state);
+        }
+    }
+
+    fn record_power_duration(&mut self) {
+        if let (Some(durations), Some(state)) = (&mut self.power_durations, self.last_power_state) {
+            durations.record(state, self.current_cores);
+        }
+    }
+
+    /// Clears the accumulated power-state durations.
+    pub fn reset_power_durations(&mut self) {
+        if let Some(durations) = &mut self.power_durations {
+            durations.reset();
+            info!("Power-state durations reset");
+        }
+    }
+
+    /// How many times each CPU was onlined or offlined since startup.
+    pub fn hotplug_counts(&self) -> &BTreeMap<usize, u64> {
+        &self.hotplug_counts
+    }
+
+    fn average_load(&self) -> f32 {
+        match self.settings.load_smoothing {
+            LoadSmoothing::Mean => self.load_tracker.get_average(),
+            LoadSmoothing::RecencyWeighted => self.load_tracker.get_weighted_average(),
+            LoadSmoothing::Ewma => self.load_tracker.get_ewma(self.settings.load_ewma_alpha),
+            LoadSmoothing::Percentile => self
+                .load_tracker
+                .get_percentile(self.settings.load_percentile),
+        }
+    }
+
+    fn selection_policy(&self) -> SelectionPolicy {
+        selection_policy_for(&self.settings)
+    }
+
+    /// Cores available to scale against: all logical CPUs, or the cgroup quota when
+    /// `respect_cgroup_quota` is set and a quota applies.
+    fn total_cores(&self) -> usize {
+        let total = self.sys.cpus().len();
+        match self.cgroup_core_limit {
+            Some(limit) => total.min(limit),
+            None => total,
+        }
+    }
+
+    fn active_profile(&self, on_battery: bool) -> Profile {
+        self.settings
+            .active_profile(
+                power_state_for(on_battery),
+                self.battery_percent.filter(|_| on_battery),
+            )
+            .into_owned()
+    }
+
+    fn core_percentage(&self, on_battery: bool) -> u32 {
+        match self.settings.no_topology_core_percentage {
+            Some(percentage) if self.topology.cores.is_empty() => percentage,
+            _ if on_battery && self.settings.interpolate_by_capacity => {
+                let battery = self.active_profile(true).core_percentage;
+                match self.battery_percent.map(|pct| pct.round() as u8) {
+                    Some(capacity) => interpolate_core_percentage(
+                        battery,
+                        self.active_profile(false).core_percentage,
+                        capacity,
+                        self.settings.capacity_band_low,
+                        self.settings.capacity_band_high,
+                    ),
+                    None => battery,
+                }
+            }
+            _ => self.active_profile(on_battery).core_percentage,
+        }
+    }
+
+    /// Polls the input idle source and logs away/back transitions.
+    fn update_input_idle(&mut self) -> bool {
+        let (Some(source), Some(threshold)) = (&mut self.idle_source, self.settings.input_idle_sec)
+        else {
+            return false;
+        };
+
+        let idle = source
+            .idle_duration()
+            .is_some_and(|idle| idle >= Duration::from_secs(threshold));
+        if idle != self.input_idle {
+            if idle {
+                info!(
+                    "No user input for {}s. Parking to {}% of cores.",
+                    threshold, self.settings.input_idle_core_percentage
+                );
+            } else {
+                info!("User input detected. Restoring normal core limits.");
+            }
+            self.input_idle = idle;
+        }
+        idle
+    }
+
+    /// Checks the inhibit lock file and logs engage/release transitions.
+    fn update_inhibit(&mut self) -> bool {
+        let inhibited = self
+            .settings
+            .inhibit_lock_path
+            .as_ref()
+            .is_some_and(|path| Path::new(path).exists());
+
+        if inhibited != self.inhibited {
+            if inhibited {
+                info!("Inhibit lock present. Holding all cores online with AC policy.");
+            } else {
+                info!("Inhibit lock removed. Resuming normal core management.");
+                self.release_hold("inhibit lock");
+            }
+            self.inhibited = inhibited;
+        }
+        inhibited
+    }
+
+    /// Common handling when any hold ends: starts the post-release grace period, during
+    /// which ramp-down is blocked so cores step down gradually afterwards.
+    fn release_hold(&mut self, hold: &str) {
+        if let Some(grace) = self.settings.post_release_grace_sec {
+            info!(
+                "{} released. Holding ramp-down for a {}s grace period.",
+                hold, grace
+            );
+            self.hold_released_at = Some(Instant::now());
+        }
+    }
+
+    fn in_release_grace(&mut self) -> bool {
+        let (Some(released), Some(grace)) =
+            (self.hold_released_at, self.settings.post_release_grace_sec)
+        else {
+            return false;
+        };
+        if released.elapsed() < Duration::from_secs(grace) {
+            return true;
+        }
+        info!("Post-release grace period over. Ramping down gradually.");
+        self.hold_released_at = None;
+        false
+    }
+
+    /// Starts the power_transition_cooldown_sec cooldown when the power state differs from
+    /// the last one seen, and reports whether it is still running.
+    fn in_power_transition_cooldown(&mut self, on_battery: bool) -> bool {
+        let Some(cooldown) = self.settings.power_transition_cooldown_sec else {
+            return false;
+        };
+        let power_state = power_state_for(on_battery);
+        if self
+            .last_power_state
+            .is_some_and(|last| last != power_state)
+        {
+            self.power_transition_at = Some(Instant::now());
+        }
+        self.power_transition_at
+            .is_some_and(|changed| changed.elapsed() < Duration::from_secs(cooldown))
+    }
+
+    /// Applies a profile's power preference through whichever lever the cpufreq driver
+    /// honours: EPP for active-mode pstate drivers, a governor otherwise. An explicit
+    /// profile governor is always written first.
+    fn apply_power_preference(&self, power_state: PowerState, profile: &Profile) {
+        if let Some(governor) = &profile.governor {
+            info!(
+                "Power state {:?}. Setting governor to '{}'",
+                power_state, governor
+            );
+            if self.settings.dry_run {
+                info!("Would set governor to '{}'", governor);
+            } else if let Err(e) = cpufreq::set_governor(governor) {
+                error!("Failed to set governor: {}", e);
+            }
+        }
+
+        match &self.cpufreq_driver {
+            CpufreqDriver::None => {
+                debug!("No cpufreq driver, skipping power preference.");
+            }
+            driver if driver.supports_epp() => {
+                info!(
+                    "Power state {:?}. Setting EPP hint to '{}'",
+                    power_state, profile.epp
+                );
+                if let Some(previous) = &self.applied_profile {
+                    self.step_epp_towards(&previous.epp, &profile.epp);
+                }
+                if let Err(e) = set_profile_epp(&self.topology, profile, self.settings.dry_run) {
+                    error!("Failed to set EPP hint: {}", e);
+                }
+            }
+            _ if profile.governor.is_some() => {}
+            _ => {
+                let governor = cpufreq::governor_for_epp(&profile.epp);
+                info!(
+                    "Power state {:?}. Setting governor to '{}'",
+                    power_state, governor
+                );
+                if self.settings.dry_run {
+                    info!("Would set governor to '{}'", governor);
+                } else if let Err(e) = cpufreq::set_governor(governor) {
+                    error!("Failed to set governor: {}", e);
+                }
+            }
+        }
+
+        if let Some(enabled) = profile.turbo {
+            if self.settings.dry_run {
+                info!("Would turn turbo {}", if enabled { "on" } else { "off" });
+            } else if let Err(e) = cpufreq::set_turbo(&self.cpufreq_driver, enabled) {
+                error!("Failed to set turbo: {}", e);
+            }
+        }
+
+        // Profiles without a cap lift one left behind by a previous profile
+        let previous_cap = self
+            .applied_profile
+            .as_ref()
+            .and_then(|applied| applied.max_freq_percent);
+        if let Some(percent) = profile.max_freq_percent.or(previous_cap.map(|_| 100)) {
+            info!("Capping CPU frequency at {}% of maximum", percent);
+            if self.settings.dry_run {
+                info!("Would cap CPU frequency at {}%", percent);
+            } else if let Err(e) = cpufreq::set_max_freq_percent(percent) {
+                error!("Failed to cap CPU frequency: {}", e);
+            }
+        }
+    }
+
+    /// Walks EPP through the intermediate hints between `from` and `to`, pausing
+    /// epp_transition_interval_ms after each. The final hint is left to the caller.
+    fn step_epp_towards(&self, from: &str, to: &str) {
+        let steps = epp::transition_steps(from, to, self.settings.epp_transition_steps);
+        if steps.is_empty() {
+            return;
+        }
+        debug!("Stepping EPP from '{}' to '{}' via {:?}", from, to, steps);
+        let interval = Duration::from_millis(self.settings.epp_transition_interval_ms);
+        for hint in steps {
+            if let Err(e) = set_epp_hint(&hint, self.settings.dry_run) {
+                warn!("Failed to set intermediate EPP hint '{}': {}", hint, e);
+                return;
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    fn update_power_state(&mut self, on_battery: bool) {
+        let current_power_state = power_state_for(on_battery);
+        if self.last_power_state != Some(current_power_state) {
+            logging::log_event(
+                Level::Info,
+                &format!("Power state changed to {:?}", current_power_state),
+                &[("POWER_STATE", format!("{:?}", current_power_state))],
+            );
+            self.last_power_state = Some(current_power_state);
+        }
+        self.record_power_duration();
+
+        let profile = self.active_profile(on_battery);
+        let latest = match &self.pending_profile {
+            Some((pending, _)) => Some(pending),
+            None => self.applied_profile.as_ref(),
+        };
+        if latest == Some(&profile) {
+            return;
+        }
+        if self.settings.epp_core_change_gap_ms.is_some()
+            && self.settings.epp_core_change_order == EppCoreOrder::CoresFirst
+        {
+            debug!("Deferring profile {:?} until after core changes", profile);
+            self.pending_profile = Some((profile, current_power_state));
+        } else {
+            self.apply_profile(profile, current_power_state);
+        }
+    }
+
+    /// Applies `profile` once, outside the adaptive loop: its core percentage as a fixed core
+    /// count, then its EPP, governor and turbo. The result stays in place when the manager
+    /// is dropped. Returns the core count applied.
+    pub fn apply_fixed_profile(&mut self, profile: Profile) -> Result<usize, Box<dyn Error>> {
+        self.keep_state_on_exit();
+        let target = percentage_limit(
+            self.total_cores(),
+            profile.core_percentage,
+            self.min_cores,
+            self.settings.percentage_rounding,
+        );
+        self.manage_cpu_cores(target)?;
+        let power_state =
+            power::get_power_state(&self.settings.power_supply_path).unwrap_or(PowerState::Unknown);
+        self.apply_profile(profile, power_state);
+        Ok(target)
+    }
+
+    fn apply_profile(&mut self, profile: Profile, power_state: PowerState) {
+        debug!("Applying profile {:?}", profile);
+        self.apply_power_preference(power_state, &profile);
+        self.applied_profile = Some(profile);
+        self.power_applied_at = Some(Instant::now());
+        self.write_session_state();
+    }
+
+    fn epp_core_change_gap(&self) -> Option<Duration> {
+        self.settings
+            .epp_core_change_gap_ms
+            .map(Duration::from_millis)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn perform_core_state_changes(&mut self, target_cores: usize) -> Result<(), Box<dyn Error>> {
+        let available_cores = self.available_cores()?;
+        let manage_from = self.settings.manage_cores_from;
+        let cores_to_enable = if self.topology.cpu_ids.is_empty() {
+            select_managed_cores(
+                &available_cores,
+                target_cores,
+                manage_from,
+                &self.topology.protected_cores,
+            )
+        } else if manage_from == 0 {
+            self.topology
+                .get_cores_to_enable(target_cores, &self.selection_policy())
+                .into_iter()
+                .collect()
+        } else {
+            let order = self
+                .topology
+                .get_cores_to_enable(usize::MAX, &self.selection_policy());
+            select_managed_cores(
+                &order,
+                target_cores,
+                manage_from,
+                &self.topology.protected_cores,
+            )
+        };
+        let online_mask = if self.online_mask_available {
+            read_online_mask(&*self.sysfs, Path::new(CPU_SYSFS_PATH))
+        } else {
+            None
+        };
+        let mut operation_successful = true;
+        let mut last_error: Option<Box<dyn Error>> = None;
+        self.deferred_disables = false;
+
+        for core_num in available_cores
+            .iter()
+            .filter(|&&core| core >= manage_from && !self.topology.protected_cores.contains(&core))
+        {
+            let should_enable = cores_to_enable.contains(core_num);
+            let cpu_state_path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, core_num);
+            let cpu_state_path = Path::new(&cpu_state_path);
+
+            let current_state_result = match &online_mask {
+                Some(mask) => Ok(mask.contains(core_num)),
+                None => self
+                    .sysfs
+                    .read(cpu_state_path)
+                    .map(|content| content.trim() == "1"),
+            };
+            let currently_enabled = match current_state_result {
+                Ok(enabled) => enabled,
+                Err(e) => {
+                    error!(
+                        "Linux: Failed to read current state for core {}: {}. Skipping change.",
+                        core_num, e
+                    );
+                    operation_successful = false;
+                    last_error = Some(e.into());
+                    continue;
+                }
+            };
+
+            if should_enable == currently_enabled {
+                continue;
+            }
+            if !should_enable {
+                if let Some(uptime) = self.uptime_short_of_minimum(*core_num) {
+                    debug!(
+                        "Deferring disable of core {}: online for {:.1}s of the required {}s",
+                        core_num,
+                        uptime.as_secs_f32(),
+                        self.settings.min_core_uptime_sec
+                    );
+                    self.deferred_disables = true;
+                    continue;
+                }
+            }
+            if self.settings.dry_run {
+                info!(
+                    "Would {} core {}",
+                    if should_enable { "enable" } else { "disable" },
+                    core_num
+                );
+                continue;
+            }
+
+            debug!(
+                "Linux: Attempting to {} core {}",
+                if should_enable { "enable" } else { "disable" },
+                core_num
+            );
+            if let Err(e) = self
+                .sysfs
+                .write(cpu_state_path, if should_enable { "1" } else { "0" })
+            {
+                let message = format!(
+                    "Linux: Failed to {} core {}: {}",
+                    if should_enable { "enable" } else { "disable" },
+                    core_num,
+                    e
+                );
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    logging::log_deduplicated(Level::Error, &message);
+                } else {
+                    error!("{}", message);
+                }
+                operation_successful = false;
+                last_error = Some(e.into());
+            } else {
+                debug!(
+                    "Linux: Core {} successfully {}",
+                    core_num,
+                    if should_enable { "enabled" } else { "disabled" }
+                );
+                let verified = self
+                    .sysfs
+                    .read(cpu_state_path)
+                    .is_ok_and(|content| (content.trim() == "1") == should_enable);
+                if should_enable {
+                    self.enabled_at.insert(*core_num, Instant::now());
+                } else {
+                    self.enabled_at.remove(core_num);
+                }
+                if verified {
+                    *self.hotplug_counts.entry(*core_num).or_insert(0) += 1;
+                } else {
+                    warn!(
+                        "Core {} did not report the requested state after the write",
+                        core_num
+                    );
+                }
+                if should_enable {
+                    thread::sleep(self.transition_delay(*core_num));
+                }
+            }
+        }
+
+        if operation_successful {
+            Ok(())
+        } else {
+            Err(last_error.unwrap_or_else(|| "Unknown error during Linux core management".into()))
+        }
+    }
+
+    /// How long `cpu` has been online, if observer onlined it less than min_core_uptime_sec
+    /// ago. Cores that were online before observer started have no minimum.
+    #[cfg(target_os = "linux")]
+    fn uptime_short_of_minimum(&self, cpu: usize) -> Option<Duration> {
+        let minimum = Duration::from_secs(self.settings.min_core_uptime_sec);
+        let uptime = self.enabled_at.get(&cpu)?.elapsed();
+        (uptime < minimum).then_some(uptime)
+    }
 
-        match self.perform_core_state_changes(target_cores) {
+    /// Settling delay after onlining `cpu`, tuned per core type on hybrid CPUs.
+    #[cfg(target_os = "linux")]
+    fn transition_delay(&self, cpu: usize) -> Duration {
+        let delay_ms = match self.topology.core_type_of(cpu) {
+            Some(CoreType::Performance) => self.settings.p_core_transition_delay_ms,
+            Some(CoreType::Efficiency) => self.settings.e_core_transition_delay_ms,
+            _ => None,
+        };
+        Duration::from_millis(delay_ms.unwrap_or(self.settings.transition_delay_ms))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn perform_core_state_changes(&self, target_cores: usize) -> Result<(), Box<dyn Error>> {
+        warn!(
+            "Core enable/disable is only supported on Linux. Requested {} cores.",
+            target_cores
+        );
+        Ok(())
+    }
+
+    fn apply_idle_injection(
+        &self,
+        device: &Path,
+        target_cores: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let total_cores = self.sys.cpus().len();
+        let percentage = powerclamp::idle_percentage(target_cores, total_cores);
+        if self.settings.dry_run {
+            info!("Would set idle injection to {}%", percentage);
+            return Ok(());
+        }
+        let applied = powerclamp::set_idle_percentage(device, percentage)?;
+        debug!(
+            "Idle injection at {}% for {} of {} cores",
+            applied, target_cores, total_cores
+        );
+        Ok(())
+    }
+
+    pub fn manage_cpu_cores(&mut self, target_cores: usize) -> Result<(), Box<dyn Error>> {
+        // Cores held back by min_core_uptime_sec are retried even at an unchanged target
+        let changing = target_cores != self.current_cores || self.deferred_disables;
+        let result = if changing {
+            self.change_core_count(target_cores)
+        } else {
+            Ok(())
+        };
+
+        // cores_first: the profile deferred by update_power_state goes out after the hotplug
+        if let Some((profile, power_state)) = self.pending_profile.take() {
+            if let (true, Some(gap)) = (changing, self.epp_core_change_gap()) {
+                thread::sleep(gap);
+            }
+            self.apply_profile(profile, power_state);
+        }
+        if changing {
+            self.write_session_state();
+        }
+        result
+    }
+
+    fn change_core_count(&mut self, target_cores: usize) -> Result<(), Box<dyn Error>> {
+        // epp_first: let the driver settle after a fresh EPP/governor write
+        if let (Some(gap), Some(applied)) = (self.epp_core_change_gap(), self.power_applied_at) {
+            let remaining = gap.saturating_sub(applied.elapsed());
+            if !remaining.is_zero() {
+                debug!(
+                    "Waiting {:?} after the EPP change before hotplug",
+                    remaining
+                );
+                thread::sleep(remaining);
+            }
+        }
+
+        let result = match &self.powerclamp_device {
+            Some(device) => self.apply_idle_injection(device, target_cores),
+            None => self.perform_core_state_changes(target_cores),
+        };
+
+        match result {
             Ok(_) => {
                 info!("Successfully adjusted cores to target: {}", target_cores);
                 self.current_cores = target_cores;
+                self.core_changes += 1;
+                self.update_extreme();
+                self.record_power_duration();
                 Ok(())
             }
             Err(e) => {
@@ -294,26 +1613,144 @@ impl CoreManager {
         }
     }
 
+    /// Fires on_max/on_min_cores_command when the core count enters either end of its range.
+    fn update_extreme(&mut self) {
+        let extreme = if self.current_cores >= self.total_cores() {
+            Some(CoreExtreme::Max)
+        } else if self.current_cores <= self.min_cores {
+            Some(CoreExtreme::Min)
+        } else {
+            None
+        };
+        if extreme == self.at_extreme {
+            return;
+        }
+        self.at_extreme = extreme;
+
+        let command = match extreme {
+            Some(CoreExtreme::Max) => self.settings.on_max_cores_command.as_deref(),
+            Some(CoreExtreme::Min) => self.settings.on_min_cores_command.as_deref(),
+            None => None,
+        };
+        if let Some(command) = command {
+            info!(
+                "Reached {:?} cores, running hook",
+                extreme.unwrap_or(CoreExtreme::Max)
+            );
+            hooks::run_command(
+                command,
+                &[("OBSERVER_CORES", self.current_cores.to_string())],
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn enable_all_cores(&self) {
+        // Runs from Drop, including while unwinding from a panic; in monitor-only mode
+        // every write would just fail with permission denied
+        if !self.capabilities.any() {
+            debug!("Linux: No writable power levers, skipping cleanup");
+            return;
+        }
+        if self.settings.dry_run {
+            self.report_dry_run_cleanup();
+            return;
+        }
+        if let Some(device) = &self.powerclamp_device {
+            info!("Linux: Cleaning up - disabling idle injection...");
+            if let Err(e) = powerclamp::set_idle_percentage(device, 0) {
+                error!("Failed to reset idle injection on shutdown: {}", e);
+            }
+        }
+
+        let available_cores = if self.capabilities.hotplug {
+            info!("Linux: Cleaning up - restoring all cores...");
+            self.core_inventory.clone()
+        } else {
+            Vec::new()
+        };
+        for core_num in available_cores
+            .iter()
+            .filter(|core| !self.topology.protected_cores.contains(core))
+        {
+            let cpu_state_path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, core_num);
+            match self.sysfs.write(Path::new(&cpu_state_path), "1") {
+                Ok(_) => debug!("Linux: Enabled core {} on shutdown.", core_num),
+                Err(e) => warn!(
+                    "Linux: Failed to enable core {} on shutdown: {}",
+                    core_num, e
+                ),
+            }
+        }
+        info!("Linux: Cleanup complete - all cores should be enabled");
+        if let (Some(governor), true) =
+            (&self.settings.default_governor, self.capabilities.governor)
+        {
+            info!("Linux: Restoring default governor ('{}')...", governor);
+            if let Err(e) = cpufreq::set_governor(governor) {
+                error!("Failed to restore default governor during cleanup: {}", e);
+            }
+        }
+        if self.cpufreq_driver.supports_epp() && self.capabilities.epp {
+            let hint = self
+                .original_epp
+                .as_deref()
+                .unwrap_or("balance_performance");
+            info!("Linux: Restoring EPP hint ('{}')...", hint);
+            if let Err(e) = set_epp_hint(hint, false) {
+                error!("Failed to restore default EPP hint during cleanup: {}", e);
+            }
+        } else if self.cpufreq_driver != CpufreqDriver::None
+            && !self.cpufreq_driver.supports_epp()
+            && self.capabilities.governor
+            && self.settings.default_governor.is_none()
+        {
+            let governor = cpufreq::governor_for_epp("balance_performance");
+            info!("Linux: Restoring default governor ('{}')...", governor);
+            if let Err(e) = cpufreq::set_governor(governor) {
+                error!("Failed to restore default governor during cleanup: {}", e);
+            }
+        }
+        if self
+            .applied_profile
+            .as_ref()
+            .is_some_and(|profile| profile.max_freq_percent.is_some())
+        {
+            info!("Linux: Lifting the CPU frequency cap...");
+            if let Err(e) = cpufreq::set_max_freq_percent(100) {
+                error!("Failed to lift the CPU frequency cap during cleanup: {}", e);
+            }
+        }
+        info!("Linux: Cleanup complete - all cores should be enabled");
+    }
+
+    /// Dry-run cleanup: nothing but EPP and core state can differ from what observer found,
+    /// so report the cores a real cleanup would bring back and the EPP it would restore.
     #[cfg(target_os = "linux")]
-    fn enable_all_cores(&self) {
-        info!("Linux: Cleaning up - restoring all cores...");
-        let available_cores = Self::get_available_cores().unwrap();
-        for core_num in available_cores.iter().skip(1) {
-            let cpu_state_path = format!("/sys/devices/system/cpu/cpu{}/online", core_num);
-            match fs::write(&cpu_state_path, "1") {
-                Ok(_) => debug!("Linux: Enabled core {} on shutdown.", core_num),
-                Err(e) => warn!(
-                    "Linux: Failed to enable core {} on shutdown: {}",
-                    core_num, e
-                ),
+    fn report_dry_run_cleanup(&self) {
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        for core_num in self
+            .core_inventory
+            .iter()
+            .filter(|core| !self.topology.protected_cores.contains(core))
+        {
+            let state = self
+                .sysfs
+                .read(&cpu_path.join(format!("cpu{}/online", core_num)));
+            if state.is_ok_and(|content| content.trim() == "0") {
+                info!("Would enable core {}", core_num);
             }
         }
-        info!("Linux: Cleanup complete - all cores should be enabled");
-        info!("Linux: Restoring default EPP hint ('balance_performance')...");
-        if let Err(e) = set_epp_hint("balance_performance") {
-            error!("Failed to restore default EPP hint during cleanup: {}", e);
+        if self.cpufreq_driver.supports_epp() {
+            let hint = self
+                .original_epp
+                .as_deref()
+                .unwrap_or("balance_performance");
+            if let Err(e) = set_epp_hint(hint, true) {
+                error!("Failed to check the default EPP hint during cleanup: {}", e);
+            }
         }
-        info!("Linux: Cleanup complete - all cores should be enabled");
+        info!("Linux: Dry run cleanup complete - nothing was written");
     }
 
     #[cfg(not(target_os = "linux"))]
@@ -322,65 +1759,974 @@ impl CoreManager {
     }
 }
 
+fn selection_policy_for(settings: &Settings) -> SelectionPolicy {
+    SelectionPolicy {
+        group_by_cache: settings.group_cores_by_cache,
+        group_by_numa: settings.group_cores_by_numa,
+        offline_priority: settings.offline_priority,
+        keep_numa_representatives: settings.keep_one_core_per_numa_node,
+        smt_siblings_first: settings.prefer_smt_offline_first,
+    }
+}
+
+fn power_state_for(on_battery: bool) -> PowerState {
+    if on_battery {
+        PowerState::Battery
+    } else {
+        PowerState::AC
+    }
+}
+
+/// Number of cores allowed by `core_percentage` of `total_cores`, rounded per `rounding` and
+/// never below `min_cores`.
+fn percentage_limit(
+    total_cores: usize,
+    core_percentage: u32,
+    min_cores: usize,
+    rounding: PercentageRounding,
+) -> usize {
+    let cores = total_cores as f32 * (core_percentage as f32 / 100.0);
+    let cores = match rounding {
+        PercentageRounding::Ceil => cores.ceil(),
+        PercentageRounding::Floor => cores.floor(),
+        PercentageRounding::Round => cores.round(),
+    };
+    cores.max(min_cores as f32) as usize
+}
+
+// A slow controller must not stall the loop; past this the local decision is used
+#[cfg(feature = "remote_target")]
+const REMOTE_TARGET_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Outcome of the load-based decision for supplied inputs, as printed by `--what-if`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub target_cores: usize,
+    pub percentage_limit: usize,
+    pub bound: &'static str, // Which rule or limit settled the target
+}
+
+/// Runs the load-based decision with `settings` for a given load and power state, without
+/// touching sysfs. Holds, interval gates and idle parking are not simulated.
+pub fn simulate_decision(
+    settings: &Settings,
+    total_cores: usize,
+    current_cores: usize,
+    load: f32,
+    on_battery: bool,
+    battery_pct: Option<f32>,
+) -> Decision {
+    let profile = settings.active_profile(
+        power_state_for(on_battery),
+        battery_pct.filter(|_| on_battery),
+    );
+    let min_cores = settings.min_cores.resolve(total_cores);
+    let limit = percentage_limit(
+        total_cores,
+        profile.core_percentage,
+        min_cores,
+        settings.percentage_rounding,
+    );
+    let use_table = !settings.load_core_table.is_empty();
+    let target_cores = if use_table {
+        table_target_cores(
+            &settings.load_core_table,
+            load,
+            total_cores,
+            min_cores,
+            limit,
+        )
+    } else {
+        compute_target_cores(
+            current_cores,
+            total_cores,
+            min_cores,
+            limit,
+            load,
+            load,
+            StepPolicy::new(settings, profile.load_threshold),
+        )
+    };
+    let clamped = clamp_change(
+        current_cores,
+        target_cores,
+        settings.max_rampdown_per_decision,
+        settings.max_rampup_per_decision,
+    );
+
+    let bound = if clamped != target_cores {
+        if clamped < current_cores {
+            "max_rampdown_per_decision"
+        } else {
+            "max_rampup_per_decision"
+        }
+    } else if use_table {
+        "load_core_table"
+    } else if total_cores <= min_cores {
+        "no room above min_cores"
+    } else if target_cores > current_cores {
+        let step = StepPolicy::new(settings, profile.load_threshold).step(load, total_cores);
+        if current_cores + step > total_cores {
+            "total cores"
+        } else {
+            "ramp-up step"
+        }
+    } else if target_cores < current_cores {
+        let step = StepPolicy::new(settings, profile.load_threshold).step(load, total_cores);
+        let stepped = current_cores.saturating_sub(step);
+        if stepped < min_cores {
+            "min_cores"
+        } else if stepped > limit {
+            "core percentage limit"
+        } else {
+            "ramp-down step"
+        }
+    } else if load > StepPolicy::new(settings, profile.load_threshold).up_threshold() {
+        "already at total cores"
+    } else if load < StepPolicy::new(settings, profile.load_threshold).down_threshold() {
+        "already at min_cores"
+    } else {
+        "load within hysteresis band"
+    };
+
+    Decision {
+        target_cores: clamped,
+        percentage_limit: limit,
+        bound,
+    }
+}
+
+/// Picks which CPUs from the enable `order` should be online for `target_cores`. CPUs below
+/// `manage_from` and protected CPUs are never toggled, so they are assumed online and count
+/// towards the target.
+#[cfg(target_os = "linux")]
+fn select_managed_cores(
+    order: &[usize],
+    target_cores: usize,
+    manage_from: usize,
+    protected: &HashSet<usize>,
+) -> HashSet<usize> {
+    let (unmanaged, managed): (Vec<usize>, Vec<usize>) = order
+        .iter()
+        .partition(|&&core| core < manage_from || protected.contains(&core));
+    let remaining = target_cores.saturating_sub(unmanaged.len());
+    unmanaged
+        .into_iter()
+        .chain(managed.into_iter().take(remaining))
+        .collect()
+}
+
+/// Knobs controlling how far `compute_target_cores` moves in one decision.
+#[derive(Debug, Clone, Copy)]
+struct StepPolicy {
+    load_threshold: f32,
+    scale_up_pct: f32,   // Ramp up above this % of load_threshold
+    scale_down_pct: f32, // Ramp down below this % of load_threshold
+    core_step: usize,
+    scaling_mode: ScalingMode,
+}
+
+impl StepPolicy {
+    fn new(settings: &Settings, load_threshold: f32) -> Self {
+        Self {
+            load_threshold,
+            scale_up_pct: settings.scale_up_threshold_pct,
+            scale_down_pct: settings.scale_down_threshold_pct,
+            core_step: settings.core_step,
+            scaling_mode: settings.scaling_mode,
+        }
+    }
+
+    fn up_threshold(&self) -> f32 {
+        self.load_threshold * self.scale_up_pct / 100.0
+    }
+
+    fn down_threshold(&self) -> f32 {
+        self.load_threshold * self.scale_down_pct / 100.0
+    }
+
+    /// Cores to add or remove at `load`. Proportional mode moves the share of
+    /// `total_cores` matching the load's relative distance from the threshold (capped at
+    /// all cores), but never less than `core_step`.
+    fn step(&self, load: f32, total_cores: usize) -> usize {
+        let fixed = self.core_step.max(1);
+        match self.scaling_mode {
+            ScalingMode::Fixed => fixed,
+            ScalingMode::Proportional => {
+                let threshold = self.load_threshold.max(f32::EPSILON);
+                let distance = ((load - threshold).abs() / threshold).min(1.0);
+                ((total_cores as f32 * distance).ceil() as usize).max(fixed)
+            }
+        }
+    }
+}
+
+/// Load-based core count decision. Steps towards the load by `policy`, never above
+/// `total_cores` and never below `min_cores`; ramp-down is also capped at `percentage_limit`.
+/// Machines with no more cores than `min_cores` simply keep all of them.
+fn compute_target_cores(
+    current_cores: usize,
+    total_cores: usize,
+    min_cores: usize,
+    percentage_limit: usize,
+    avg_load: f32,
+    rampup_load: f32,
+    policy: StepPolicy,
+) -> usize {
+    if total_cores <= min_cores {
+        return total_cores;
+    }
+
+    if rampup_load > policy.up_threshold() && current_cores < total_cores {
+        (current_cores + policy.step(rampup_load, total_cores)).min(total_cores)
+    } else if avg_load < policy.down_threshold() && current_cores > min_cores {
+        (current_cores.saturating_sub(policy.step(avg_load, total_cores)))
+            .max(min_cores)
+            .min(percentage_limit)
+    } else {
+        current_cores
+    }
+}
+
+/// Evaluations in a row with load below the scale-down threshold needed before ramping
+/// down straight after a ramp-up.
+const SCALE_DOWN_CONFIRMATIONS: u32 = 2;
+
+/// Holds a ramp-down that would directly reverse a ramp-up until load has stayed below the
+/// scale-down threshold for SCALE_DOWN_CONFIRMATIONS evaluations, so a load hovering
+/// around the threshold can't flap the core count.
+fn confirm_rampdown(
+    current_cores: usize,
+    target_cores: usize,
+    last_direction: Option<Ordering>,
+    below_down_streak: u32,
+) -> usize {
+    if target_cores < current_cores
+        && last_direction == Some(Ordering::Greater)
+        && below_down_streak < SCALE_DOWN_CONFIRMATIONS
+    {
+        current_cores
+    } else {
+        target_cores
+    }
+}
+
+/// Reads a remote controller's reply: a bare core count or `{"target_cores": N}`.
+#[cfg(feature = "remote_target")]
+fn parse_remote_target(body: &str) -> Result<usize, String> {
+    let body = body.trim();
+    if let Ok(target) = body.parse() {
+        return Ok(target);
+    }
+    serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("target_cores")?.as_u64())
+        .map(|target| target as usize)
+        .ok_or_else(|| format!("unexpected response '{}'", body))
+}
+
+/// Looks up the core count for `load` in `table`: the entry with the highest load
+/// breakpoint at or below `load`, or the lowest entry when load is below them all. The
+/// result is capped at `percentage_limit` and kept within `min_cores..=total_cores`.
+fn table_target_cores(
+    table: &[(f32, usize)],
+    load: f32,
+    total_cores: usize,
+    min_cores: usize,
+    percentage_limit: usize,
+) -> usize {
+    let entry = table
+        .iter()
+        .filter(|(breakpoint, _)| *breakpoint <= load)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .or_else(|| table.iter().min_by(|a, b| a.0.total_cmp(&b.0)));
+    let cores = entry.map_or(total_cores, |(_, cores)| *cores);
+    cores.min(percentage_limit).max(min_cores).min(total_cores)
+}
+
+/// Core percentage on battery at `capacity`: the battery percentage at or below `low`, the
+/// AC percentage at or above `high`, and a smoothstep between them across the band.
+fn interpolate_core_percentage(battery: u32, ac: u32, capacity: u8, low: u8, high: u8) -> u32 {
+    if high <= low {
+        return if capacity >= high { ac } else { battery };
+    }
+    let t = ((f32::from(capacity) - f32::from(low)) / f32::from(high - low)).clamp(0.0, 1.0);
+    let weight = t * t * (3.0 - 2.0 * t);
+    (battery as f32 + (ac as f32 - battery as f32) * weight).round() as u32
+}
+
+/// Averages `(cpu, usage)` samples overall and per core type. Usage is per CPU (0-100), so
+/// the result doesn't grow or shrink with the number of CPUs online.
+fn summarize_load(topology: &CPUTopology, samples: &[(usize, f32)]) -> LoadSnapshot {
+    let average = |core_type: Option<CoreType>| {
+        let usages: Vec<f32> = samples
+            .iter()
+            .filter(|(cpu, _)| core_type.is_none() || topology.core_type_of(*cpu) == core_type)
+            .map(|(_, usage)| *usage)
+            .collect();
+        if usages.is_empty() {
+            0.0
+        } else {
+            usages.iter().sum::<f32>() / usages.len() as f32
+        }
+    };
+    LoadSnapshot {
+        avg: average(None),
+        p_core_avg: average(Some(CoreType::Performance)),
+        e_core_avg: average(Some(CoreType::Efficiency)),
+    }
+}
+
+/// Blends `samples` into `averages` with weight `alpha` on the new sample. A change in CPU
+/// count restarts the averages from the samples.
+fn ewma_update(averages: &mut Vec<f32>, samples: &[f32], alpha: f32) {
+    let alpha = alpha.clamp(0.01, 1.0);
+    if averages.len() != samples.len() {
+        *averages = samples.to_vec();
+        return;
+    }
+    for (average, sample) in averages.iter_mut().zip(samples) {
+        *average += alpha * (sample - *average);
+    }
+}
+
+/// Limits how far `target` may move from `current` in one decision. A cap of 0 is treated
+/// as 1 so the count can still move.
+fn clamp_change(
+    current: usize,
+    target: usize,
+    max_rampdown: Option<usize>,
+    max_rampup: Option<usize>,
+) -> usize {
+    if target < current {
+        match max_rampdown {
+            Some(max) => target.max(current.saturating_sub(max.max(1))),
+            None => target,
+        }
+    } else {
+        match max_rampup {
+            Some(max) => target.min(current + max.max(1)),
+            None => target,
+        }
+    }
+}
+
+/// Suggests a config change when the battery floor on a hybrid CPU would keep only P-cores
+/// online, which usually defeats the point of parking cores on battery.
+fn battery_core_advisory(settings: &Settings, topology: &CPUTopology) -> Option<String> {
+    if topology.num_e_cores == 0 || topology.num_p_cores == 0 {
+        return None;
+    }
+
+    let total_cores = topology.cpu_ids.len();
+    let battery_percentage = settings
+        .active_profile(PowerState::Battery, None)
+        .core_percentage;
+    let floor = percentage_limit(
+        total_cores,
+        battery_percentage,
+        settings.min_cores.resolve(total_cores),
+        settings.percentage_rounding,
+    );
+    if floor >= total_cores {
+        return None;
+    }
+
+    let policy = selection_policy_for(settings);
+    let battery_set = topology.get_cores_to_enable(floor, &policy);
+    let has_e_cores = battery_set
+        .iter()
+        .any(|&cpu| topology.core_type_of(cpu) == Some(CoreType::Efficiency));
+    if has_e_cores {
+        return None;
+    }
+
+    Some(format!(
+        "Battery core percentage {} keeps only P-cores online on battery ({} of {} cores). \
+         Consider offline_priority = \"performance\" to keep the more efficient E-cores instead.",
+        battery_percentage, floor, total_cores
+    ))
+}
+
 impl Drop for CoreManager {
     fn drop(&mut self) {
-        self.enable_all_cores();
+        if self.restore_on_exit {
+            self.enable_all_cores();
+        }
+        // Cores are restored, so there is nothing left for a watchdog or the next start to do
+        for path in [&self.settings.deadman_path, &self.settings.state_file]
+            .into_iter()
+            .flatten()
+        {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::config::OfflinePriority;
+    use crate::core::topology::tests::{fake_cpu_sysfs, remove_fake_sysfs};
+    use crate::system::sysfs::MockSysfs;
+
+    #[test]
+    fn test_unmanaged_cores_are_kept() {
+        // P-cores 4-7 are preferred, but 0-3 belong to firmware and must stay as they are
+        let order = [0, 4, 5, 6, 7, 1, 2, 3];
+        let selected = select_managed_cores(&order, 6, 4, &HashSet::new());
+        assert!((0..4).all(|core| selected.contains(&core)));
+        assert_eq!(selected.len(), 6);
+        assert!(selected.contains(&4) && selected.contains(&5));
+
+        // Asking for fewer cores than the unmanaged range never drops any of them
+        assert_eq!(select_managed_cores(&order, 2, 4, &HashSet::new()).len(), 4);
+
+        // manage_cores_from = 0 keeps the plain enable order
+        let selected = select_managed_cores(&order, 3, 0, &HashSet::new());
+        assert_eq!(selected, HashSet::from([0, 4, 5]));
+
+        // Protected CPUs are kept wherever they sit in the order and count towards the target
+        let selected = select_managed_cores(&order, 3, 0, &HashSet::from([0, 7]));
+        assert_eq!(selected.len(), 3);
+        assert!(selected.contains(&0) && selected.contains(&7));
+    }
+
+    #[test]
+    fn test_simulate_decision() {
+        let settings = Settings::default();
+        // Battery: threshold 45%, 50% of 8 cores
+        let decision = simulate_decision(&settings, 8, 8, 10.0, true, None);
+        assert_eq!(decision.target_cores, 4);
+        assert_eq!(decision.percentage_limit, 4);
+        assert_eq!(decision.bound, "core percentage limit");
+
+        let decision = simulate_decision(&settings, 8, 7, 99.0, false, None);
+        assert_eq!(decision.target_cores, 8);
+        assert_eq!(decision.bound, "total cores");
+
+        let decision = simulate_decision(&settings, 8, 4, 45.0, true, None);
+        assert_eq!(decision.target_cores, 4);
+        assert_eq!(decision.bound, "load within hysteresis band");
+    }
+
+    #[test]
+    fn test_compute_target_cores_small_machines() {
+        let fixed = StepPolicy::new(&Settings::default(), 45.0);
+        // 1-core machine with the default min_cores of 2: nothing to scale
+        let limit = percentage_limit(1, 50, 2, PercentageRounding::Ceil);
+        assert_eq!(compute_target_cores(1, 1, 2, limit, 99.0, 99.0, fixed), 1);
+        assert_eq!(compute_target_cores(1, 1, 2, limit, 1.0, 1.0, fixed), 1);
+
+        // 2-core machine with min_cores of 2: all cores stay online regardless of load
+        let limit = percentage_limit(2, 50, 2, PercentageRounding::Ceil);
+        assert_eq!(compute_target_cores(2, 2, 2, limit, 99.0, 99.0, fixed), 2);
+        assert_eq!(compute_target_cores(2, 2, 2, limit, 1.0, 1.0, fixed), 2);
+
+        // With room to scale the regular steps apply
+        let limit = percentage_limit(8, 50, 2, PercentageRounding::Ceil);
+        assert_eq!(compute_target_cores(4, 8, 2, limit, 99.0, 99.0, fixed), 6);
+        assert_eq!(compute_target_cores(6, 8, 2, limit, 1.0, 1.0, fixed), 4);
+    }
+
+    #[test]
+    fn test_hysteresis_stops_flapping() {
+        let settings = Settings::default();
+        let policy = StepPolicy::new(&settings, 50.0);
+        // Alternates just above the ramp-up (60%) and just below the ramp-down (40%) threshold
+        let run = |confirm: bool| {
+            let (mut cores, mut last_direction, mut streak) = (4, None, 0);
+            let mut counts = Vec::new();
+            for load in [65.0, 35.0].repeat(6) {
+                streak = if load < policy.down_threshold() {
+                    streak + 1
+                } else {
+                    0
+                };
+                let mut target = compute_target_cores(cores, 8, 2, 8, load, load, policy);
+                if confirm {
+                    target = confirm_rampdown(cores, target, last_direction, streak);
+                }
+                if target != cores {
+                    last_direction = Some(target.cmp(&cores));
+                    cores = target;
+                }
+                counts.push(cores);
+            }
+            counts
+        };
+
+        let unconfirmed = run(false);
+        assert_eq!(&unconfirmed[8..], &[6, 4, 6, 4]);
+        let confirmed = run(true);
+        assert_eq!(&confirmed[..4], &[6, 6, 8, 8]);
+        assert!(confirmed[4..].iter().all(|&cores| cores == 8));
+
+        // Load that stays low does ramp down after the confirmations
+        let mut streak = 0;
+        let mut held = Vec::new();
+        for _ in 0..3 {
+            streak += 1;
+            let target = compute_target_cores(8, 8, 2, 8, 20.0, 20.0, policy);
+            held.push(confirm_rampdown(8, target, Some(Ordering::Greater), streak));
+        }
+        assert_eq!(held, vec![8, 6, 6]);
+
+        let custom = Settings {
+            scale_up_threshold_pct: 150.0,
+            scale_down_threshold_pct: 50.0,
+            ..Settings::default()
+        };
+        let wide = StepPolicy::new(&custom, 50.0);
+        assert_eq!(compute_target_cores(4, 8, 2, 8, 65.0, 65.0, wide), 4);
+        assert_eq!(compute_target_cores(4, 8, 2, 8, 35.0, 35.0, wide), 4);
     }
+
+    #[test]
+    fn test_core_step_and_proportional_scaling() {
+        let mut settings = Settings {
+            core_step: 4,
+            ..Settings::default()
+        };
+        let limit = 64;
+        let fixed = StepPolicy::new(&settings, 50.0);
+        assert_eq!(compute_target_cores(8, 64, 2, limit, 70.0, 70.0, fixed), 12);
+        assert_eq!(compute_target_cores(8, 64, 2, limit, 30.0, 30.0, fixed), 4);
+
+        // 40% above the threshold moves 40% of 64 cores; just past it falls back to core_step
+        settings.scaling_mode = ScalingMode::Proportional;
+        let proportional = StepPolicy::new(&settings, 50.0);
+        assert_eq!(
+            compute_target_cores(8, 64, 2, limit, 70.0, 70.0, proportional),
+            34
+        );
+        assert_eq!(proportional.step(61.0, 16), 4);
+        // Idle drops straight to min_cores, and the result stays within the limits
+        assert_eq!(
+            compute_target_cores(40, 64, 2, limit, 0.0, 0.0, proportional),
+            2
+        );
+        assert_eq!(
+            compute_target_cores(40, 64, 2, 32, 35.0, 35.0, proportional),
+            20
+        );
+        assert_eq!(
+            compute_target_cores(60, 64, 2, limit, 200.0, 200.0, proportional),
+            64
+        );
+    }
+
+    #[cfg(feature = "remote_target")]
+    #[test]
+    fn test_parse_remote_target() {
+        assert_eq!(parse_remote_target("6\n"), Ok(6));
+        assert_eq!(parse_remote_target(r#"{"target_cores": 4}"#), Ok(4));
+        assert!(parse_remote_target("<html>busy</html>").is_err());
+    }
+
+    #[test]
+    fn test_load_core_table_lookup() {
+        let table = [(0.0, 2), (20.0, 4), (60.0, usize::MAX)];
+        assert_eq!(table_target_cores(&table, 5.0, 16, 2, 16), 2);
+        assert_eq!(table_target_cores(&table, 20.0, 16, 2, 16), 4);
+        assert_eq!(table_target_cores(&table, 59.9, 16, 2, 16), 4);
+        assert_eq!(table_target_cores(&table, 95.0, 16, 2, 16), 16);
+        // Battery percentage limit and min_cores still apply
+        assert_eq!(table_target_cores(&table, 95.0, 16, 2, 8), 8);
+        assert_eq!(table_target_cores(&[(10.0, 1)], 5.0, 16, 2, 16), 2);
+
+        let settings = Settings {
+            load_core_table: table.to_vec(),
+            ..Settings::default()
+        };
+        let decision = simulate_decision(&settings, 16, 16, 30.0, false, None);
+        assert_eq!(decision.target_cores, 4);
+        assert_eq!(decision.bound, "load_core_table");
+    }
+
+    #[test]
+    fn test_interpolate_core_percentage() {
+        assert_eq!(interpolate_core_percentage(50, 100, 10, 20, 80), 50);
+        assert_eq!(interpolate_core_percentage(50, 100, 20, 20, 80), 50);
+        assert_eq!(interpolate_core_percentage(50, 100, 50, 20, 80), 75);
+        assert_eq!(interpolate_core_percentage(50, 100, 35, 20, 80), 58);
+        assert_eq!(interpolate_core_percentage(50, 100, 95, 20, 80), 100);
+        assert_eq!(interpolate_core_percentage(50, 100, 60, 50, 50), 100);
+    }
+
+    #[test]
+    fn test_ewma_update() {
+        let mut averages = Vec::new();
+        ewma_update(&mut averages, &[100.0, 0.0], 0.5);
+        assert_eq!(averages, vec![100.0, 0.0]);
+        ewma_update(&mut averages, &[0.0, 40.0], 0.5);
+        assert_eq!(averages, vec![50.0, 20.0]);
+        ewma_update(&mut averages, &[0.0, 40.0], 1.0);
+        assert_eq!(averages, vec![0.0, 40.0]);
+        ewma_update(&mut averages, &[10.0, 10.0, 10.0], 0.5);
+        assert_eq!(averages, vec![10.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    fn test_summarize_load_per_core_type() {
+        let sysfs = mock_cpu_sysfs();
+        let topology = CPUTopology::from_provider(&*sysfs, Path::new(CPU_SYSFS_PATH));
+        // CPU3 (an E-core) is offline, so it isn't sampled
+        let load = summarize_load(&topology, &[(0, 90.0), (1, 70.0), (2, 20.0)]);
+        assert_eq!(load.avg, 60.0);
+        assert_eq!(load.p_core_avg, 80.0);
+        assert_eq!(load.e_core_avg, 20.0);
+
+        let load = summarize_load(&CPUTopology::default(), &[(0, 50.0), (1, 30.0)]);
+        assert_eq!(load.avg, 40.0);
+        assert_eq!(load.p_core_avg, 0.0);
+        assert_eq!(summarize_load(&topology, &[]), LoadSnapshot::default());
+    }
+
+    #[test]
+    fn test_percentage_limit_rounding() {
+        assert_eq!(percentage_limit(9, 50, 2, PercentageRounding::Ceil), 5);
+        assert_eq!(percentage_limit(9, 50, 2, PercentageRounding::Floor), 4);
+        assert_eq!(percentage_limit(9, 50, 2, PercentageRounding::Round), 5);
+        assert_eq!(percentage_limit(9, 40, 2, PercentageRounding::Round), 4);
+        assert_eq!(percentage_limit(3, 40, 2, PercentageRounding::Floor), 2);
+    }
+
+    #[test]
+    fn test_clamp_change_bounds_large_deltas() {
+        assert_eq!(clamp_change(16, 4, Some(2), None), 14);
+        assert_eq!(clamp_change(16, 15, Some(2), None), 15);
+        assert_eq!(clamp_change(4, 16, None, Some(3)), 7);
+        assert_eq!(clamp_change(4, 16, Some(2), None), 16);
+        assert_eq!(clamp_change(16, 4, None, None), 4);
+        assert_eq!(clamp_change(16, 4, Some(0), None), 15);
+
+        // Unplugging drops straight to the battery limit unless clamped
+        let mut settings = Settings::default();
+        let decision = simulate_decision(&settings, 16, 16, 1.0, true, None);
+        assert_eq!(decision.target_cores, 8);
+        settings.max_rampdown_per_decision = Some(2);
+        let decision = simulate_decision(&settings, 16, 16, 1.0, true, None);
+        assert_eq!(decision.target_cores, 14);
+        assert_eq!(decision.bound, "max_rampdown_per_decision");
+    }
+
+    #[test]
+    fn test_battery_core_advisory() {
+        let root = fake_cpu_sysfs(
+            "advisory",
+            &[
+                (0, "0", 4000000),
+                (1, "1", 4000000),
+                (2, "2", 2000000),
+                (3, "3", 2000000),
+            ],
+        );
+        let topology = CPUTopology::from_sysfs(&root);
+        let mut settings = Settings::default();
+
+        assert!(battery_core_advisory(&settings, &topology).is_some());
+
+        settings.offline_priority = OfflinePriority::Performance;
+        assert!(battery_core_advisory(&settings, &topology).is_none());
+
+        settings.offline_priority = OfflinePriority::Efficiency;
+        settings.battery_core_percentage = 75;
+        assert!(battery_core_advisory(&settings, &topology).is_none());
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_sparse_available_and_online_cores() {
+        let root = fake_cpu_sysfs(
+            "sparse-manager",
+            &[
+                (0, "0", 4000000),
+                (1, "1", 4000000),
+                (4, "4", 4000000),
+                (5, "5", 4000000),
+            ],
+        );
+        fs::write(root.join("cpu5/online"), "0").unwrap();
+
+        let cores = CoreManager::available_cores_in(&LinuxSysfs, &root).unwrap();
+        assert_eq!(cores, vec![0, 1, 4, 5]);
+        assert_eq!(CoreManager::count_online_cores(&LinuxSysfs, &root), 3);
+
+        remove_fake_sysfs(&root);
+    }
+
+    /// Two P-cores (0-1) and two E-cores (2-3) without SMT, all online.
+    fn mock_cpu_sysfs() -> Arc<MockSysfs> {
+        let sysfs = Arc::new(MockSysfs::new());
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        for (cpu, freq) in [
+            (0, "4000000"),
+            (1, "4000000"),
+            (2, "2000000"),
+            (3, "2000000"),
+        ] {
+            let dir = cpu_path.join(format!("cpu{}", cpu));
+            sysfs.insert(dir.join("topology/thread_siblings_list"), &cpu.to_string());
+            sysfs.insert(dir.join("cpufreq/scaling_max_freq"), freq);
+            if cpu != 0 {
+                sysfs.insert(dir.join("online"), "1");
+            }
+        }
+        sysfs
+    }
+
+    #[test]
+    fn test_core_state_changes_on_mock_sysfs() {
+        let sysfs = mock_cpu_sysfs();
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        let online = |cpu: usize| sysfs.get(cpu_path.join(format!("cpu{}/online", cpu)));
+
+        let settings = Settings {
+            transition_delay_ms: 0,
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings, sysfs.clone()).unwrap();
+        assert_eq!(manager.topology.num_p_cores, 2);
+        assert_eq!(manager.topology.num_e_cores, 2);
+
+        // E-cores go offline first, so two cores leave only the P-cores online
+        manager.perform_core_state_changes(2).unwrap();
+        assert_eq!(online(1).as_deref(), Some("1"));
+        assert_eq!(online(2).as_deref(), Some("0"));
+        assert_eq!(online(3).as_deref(), Some("0"));
+        assert_eq!(manager.hotplug_counts.values().sum::<u64>(), 2);
+
+        manager.perform_core_state_changes(4).unwrap();
+        assert!((1..4).all(|cpu| online(cpu).as_deref() == Some("1")));
+        assert_eq!(manager.hotplug_counts.values().sum::<u64>(), 4);
+    }
+
+    #[test]
+    fn test_core_inventory_rescanned_on_hot_remove() {
+        let sysfs = mock_cpu_sysfs();
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        let settings = Settings {
+            transition_delay_ms: 0,
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings, sysfs.clone()).unwrap();
+        assert_eq!(manager.core_inventory, vec![0, 1, 2, 3]);
+
+        // Without cpu_hotplug_aware the cached list is kept, so the vanished core fails
+        sysfs.remove(cpu_path.join("cpu3"));
+        assert!(manager.perform_core_state_changes(2).is_err());
+        assert_eq!(manager.core_inventory, vec![0, 1, 2, 3]);
+
+        manager.settings.cpu_hotplug_aware = true;
+        manager.perform_core_state_changes(2).unwrap();
+        assert_eq!(manager.core_inventory, vec![0, 1, 2]);
+
+        sysfs.insert(cpu_path.join("cpu3/online"), "1");
+        manager.refresh_core_inventory();
+        assert_eq!(manager.core_inventory, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_min_core_uptime_defers_disable() {
+        let sysfs = mock_cpu_sysfs();
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        let online = |cpu: usize| sysfs.get(cpu_path.join(format!("cpu{}/online", cpu)));
+        let settings = Settings {
+            transition_delay_ms: 0,
+            min_core_uptime_sec: 60,
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings, sysfs.clone()).unwrap();
+
+        // Cores that were online at startup can go straight away
+        manager.perform_core_state_changes(2).unwrap();
+        assert_eq!(online(2).as_deref(), Some("0"));
+        manager.perform_core_state_changes(4).unwrap();
+        manager.perform_core_state_changes(2).unwrap();
+        assert_eq!(online(2).as_deref(), Some("1"));
+        assert_eq!(online(3).as_deref(), Some("1"));
+        assert!(manager.deferred_disables);
+
+        let long_ago = Instant::now() - Duration::from_secs(61);
+        manager.enabled_at.insert(2, long_ago);
+        manager.perform_core_state_changes(2).unwrap();
+        assert_eq!(online(2).as_deref(), Some("0"));
+        assert_eq!(online(3).as_deref(), Some("1"));
+        assert!(manager.deferred_disables);
+    }
+
+    #[test]
+    fn test_low_battery_profile_follows_charge() {
+        let power_path =
+            std::env::temp_dir().join(format!("observer-low-battery-{}", std::process::id()));
+        fs::create_dir_all(power_path.join("BAT0")).unwrap();
+        fs::write(power_path.join("BAT0/type"), "Battery").unwrap();
+        fs::write(power_path.join("BAT0/capacity"), "10").unwrap();
+
+        let quiet = Profile {
+            core_percentage: 25,
+            epp: "power".to_string(),
+            ..Profile::default()
+        };
+        let mut settings = Settings {
+            low_battery_profile: Some("quiet".to_string()),
+            power_supply_path: power_path.to_string_lossy().into_owned(),
+            cpu_sample_interval_ms: 0,
+            transition_delay_ms: 0,
+            state_file: None,
+            ..Settings::default()
+        };
+        settings.profiles.insert("quiet".to_string(), quiet.clone());
+        let mut manager = CoreManager::with_sysfs(settings, mock_cpu_sysfs()).unwrap();
+
+        manager.get_optimal_core_count(true).unwrap();
+        assert_eq!(manager.battery_percent, Some(10.0));
+        assert_eq!(manager.applied_profile.as_ref(), Some(&quiet));
+
+        fs::write(power_path.join("BAT0/capacity"), "80").unwrap();
+        manager.get_optimal_core_count(true).unwrap();
+        assert_eq!(
+            manager.applied_profile.as_ref().unwrap().epp,
+            "balance_power"
+        );
+
+        fs::remove_dir_all(power_path).unwrap();
+    }
+
+    #[test]
+    fn test_one_shot_skips_change_gates() {
+        // A 1% battery limit pins the target at one core whatever the host's load and size
+        let settings = Settings {
+            battery_core_percentage: 1,
+            min_cores: MinCores::Count(1),
+            power_supply_path: "/nonexistent".to_string(),
+            cpu_sample_interval_ms: 0,
+            transition_delay_ms: 0,
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings.clone(), mock_cpu_sysfs()).unwrap();
+        assert_eq!(manager.get_optimal_core_count(true).unwrap(), 4);
+
+        let mut manager = CoreManager::with_sysfs(settings, mock_cpu_sysfs()).unwrap();
+        manager.one_shot();
+        assert_eq!(manager.get_optimal_core_count(true).unwrap(), 1);
+        assert!(!manager.restore_on_exit);
+    }
+
+    #[test]
+    fn test_power_transition_cooldown() {
+        let settings = Settings {
+            power_transition_cooldown_sec: Some(10),
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings, mock_cpu_sysfs()).unwrap();
+
+        // The first power state seen at startup is not a transition
+        assert!(!manager.in_power_transition_cooldown(false));
+        manager.last_power_state = Some(PowerState::AC);
+        assert!(!manager.in_power_transition_cooldown(false));
+        assert!(manager.in_power_transition_cooldown(true));
+
+        manager.last_power_state = Some(PowerState::Battery);
+        manager.power_transition_at = Some(Instant::now() - Duration::from_secs(11));
+        assert!(!manager.in_power_transition_cooldown(true));
+    }
+
+    #[test]
+    fn test_recovers_cores_from_previous_session() {
+        let sysfs = mock_cpu_sysfs();
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        for cpu in [2, 3] {
+            sysfs
+                .write(&cpu_path.join(format!("cpu{}/online", cpu)), "0")
+                .unwrap();
+        }
+        let state_path =
+            std::env::temp_dir().join(format!("observer-recover-{}.json", std::process::id()));
+        SessionState {
+            pid: 1,
+            offline_cores: vec![2, 3],
+            original_epp: None,
+        }
+        .write_to(&state_path)
+        .unwrap();
+
+        let settings = Settings {
+            state_file: Some(state_path.to_string_lossy().into_owned()),
+            ..Settings::default()
+        };
+        let manager = CoreManager::with_sysfs(settings, sysfs.clone()).unwrap();
+        assert!((1..4).all(|cpu| {
+            sysfs
+                .get(cpu_path.join(format!("cpu{}/online", cpu)))
+                .as_deref()
+                == Some("1")
+        }));
+        assert_eq!(manager.current_cores, 4);
+        assert!(!state_path.exists());
+    }
+
+    #[test]
+    fn test_dry_run_leaves_sysfs_untouched() {
+        let sysfs = mock_cpu_sysfs();
+        let settings = Settings {
+            transition_delay_ms: 0,
+            dry_run: true,
+            state_file: None,
+            ..Settings::default()
+        };
+        let mut manager = CoreManager::with_sysfs(settings, sysfs.clone()).unwrap();
+
+        manager.perform_core_state_changes(1).unwrap();
+        let cpu_path = Path::new(CPU_SYSFS_PATH);
+        assert!((1..4).all(|cpu| {
+            sysfs
+                .get(cpu_path.join(format!("cpu{}/online", cpu)))
+                .as_deref()
+                == Some("1")
+        }));
+        assert!(manager.hotplug_counts.is_empty());
+    }
+}
+
+// Systems without cpufreq (some VMs and ARM boards) only hear about it once
+#[cfg(target_os = "linux")]
+static CPUFREQ_MISSING_LOGGED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_os = "linux")]
+fn cpufreq_available() -> bool {
+    if Path::new(epp::CPUFREQ_SYSFS_PATH).is_dir() {
+        return true;
+    }
+    if !CPUFREQ_MISSING_LOGGED.swap(true, AtomicOrdering::Relaxed) {
+        info!("cpufreq not available, skipping EPP");
+    }
+    false
 }
 
 #[cfg(target_os = "linux")]
-fn set_epp_hint(hint: &str) -> Result<(), String> {
+fn set_epp_hint(hint: &str, dry_run: bool) -> Result<(), String> {
+    if !cpufreq_available() {
+        return Ok(());
+    }
+
     debug!("Attempting to set EPP hint to '{}' for all policies", hint);
-    let base_path = Path::new("/sys/devices/system/cpu/cpufreq");
     let mut policies_updated = 0;
 
-    for entry in fs::read_dir(base_path)
-        .map_err(|e| format!("Failed to read {}: {}", base_path.display(), e))?
-    {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
-        let path = entry.path();
-        if path.is_dir() {
-            if let Some(name) = path.file_name() {
-                if name.to_string_lossy().starts_with("policy") {
-                    let epp_path = path.join("energy_performance_preference");
-                    if epp_path.exists() {
-                        match fs::write(&epp_path, hint) {
-                            Ok(_) => {
-                                debug!(
-                                    "Successfully set EPP for {} to '{}'",
-                                    name.to_string_lossy(),
-                                    hint
-                                );
-                                policies_updated += 1;
-                            }
-                            Err(e) => {
-                                if e.kind() == std::io::ErrorKind::PermissionDenied {
-                                    error!(
-                                        "Permission denied writing to {}. Run observer with sudo?",
-                                        epp_path.display()
-                                    );
-                                    return Err(format!(
-                                        "Permission denied for {}",
-                                        epp_path.display()
-                                    ));
-                                } else {
-                                    warn!("Failed to write to {}: {}. Check permissions or if file is writable.", epp_path.display(), e);
-                                }
-                            }
-                        }
-                    } else {
-                        debug!(
-                            "EPP file not found for {}: {}",
-                            name.to_string_lossy(),
-                            epp_path.display()
-                        );
-                    }
-                }
-            }
+    for policy in epp::list_epp_policies() {
+        if write_policy_epp(policy, hint, dry_run)? {
+            policies_updated += 1;
         }
     }
 
-    if policies_updated == 0 {
+    if policies_updated == 0 && !dry_run {
         warn!(
             "Could not set EPP hint for any CPU policy. Is intel_pstate active and EPP available?"
         );
@@ -388,11 +2734,93 @@ fn set_epp_hint(hint: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Sets the profile's EPP, giving P-core and E-core policies their own hint where the
+/// profile has one. A policy's type is that of the first CPU in its `affected_cpus`;
+/// policies of unknown type get `profile.epp`.
+#[cfg(target_os = "linux")]
+fn set_profile_epp(topology: &CPUTopology, profile: &Profile, dry_run: bool) -> Result<(), String> {
+    if profile.epp_pcore.is_none() && profile.epp_ecore.is_none() {
+        return set_epp_hint(&profile.epp, dry_run);
+    }
+    if !cpufreq_available() {
+        return Ok(());
+    }
+
+    let (mut p_policies, mut e_policies, mut other_policies) = (0, 0, 0);
+    for policy in epp::list_epp_policies() {
+        let core_type = epp::policy_cpus(policy)
+            .first()
+            .and_then(|&cpu| topology.core_type_of(cpu));
+        let (hint, count) = match core_type {
+            Some(CoreType::Performance) => (profile.epp_pcore.as_ref(), &mut p_policies),
+            Some(CoreType::Efficiency) => (profile.epp_ecore.as_ref(), &mut e_policies),
+            _ => (None, &mut other_policies),
+        };
+        if write_policy_epp(policy, hint.unwrap_or(&profile.epp), dry_run)? {
+            *count += 1;
+        }
+    }
+
+    if !dry_run {
+        info!(
+            "Set EPP on {} P-core, {} E-core and {} other policies",
+            p_policies, e_policies, other_policies
+        );
+        if p_policies + e_policies + other_policies == 0 {
+            warn!(
+                "Could not set EPP hint for any CPU policy. Is intel_pstate active and EPP available?"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes `hint` to one policy, or logs what would change in dry-run mode. True when the
+/// hint was written. Permission problems and invalid hints end the whole update.
+#[cfg(target_os = "linux")]
+fn write_policy_epp(policy: usize, hint: &str, dry_run: bool) -> Result<bool, String> {
+    if dry_run {
+        match epp::epp_would_change(policy, hint) {
+            Ok(true) => info!("Would set EPP for policy{} to '{}'", policy, hint),
+            Ok(false) => {}
+            Err(e) => warn!("Cannot check EPP for policy{}: {}", policy, e),
+        }
+        return Ok(false);
+    }
+    match epp::set_epp_for_policy(policy, hint) {
+        Ok(()) => Ok(true),
+        Err(CoreError::PermissionDenied(path)) => {
+            logging::log_deduplicated(
+                Level::Error,
+                &format!(
+                    "Permission denied writing to {}. Run observer with sudo?",
+                    path.display()
+                ),
+            );
+            Err(format!("Permission denied for {}", path.display()))
+        }
+        Err(CoreError::InvalidValue(msg)) => Err(msg),
+        Err(e) => {
+            warn!("Failed to set EPP for policy{}: {}", policy, e);
+            Ok(false)
+        }
+    }
+}
+
 #[cfg(not(target_os = "linux"))]
-fn set_epp_hint(hint: &str) -> Result<(), String> {
+fn set_epp_hint(hint: &str, _dry_run: bool) -> Result<(), String> {
     warn!(
         "EPP setting is only supported on Linux. Hint '{}' ignored.",
         hint
     );
     Ok(())
 }
+
+#[cfg(not(target_os = "linux"))]
+fn set_profile_epp(
+    _topology: &CPUTopology,
+    profile: &Profile,
+    dry_run: bool,
+) -> Result<(), String> {
+    set_epp_hint(&profile.epp, dry_run)
+}
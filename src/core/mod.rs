@@ -1,5 +1,10 @@
+pub mod epp;
+pub mod error;
 mod load_tracker;
 pub mod manager;
+mod power_durations;
+pub mod session_state;
+pub mod snapshot;
 pub mod topology;
 
 pub use manager::CoreManager;
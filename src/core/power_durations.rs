@@ -0,0 +1,120 @@
+use crate::system::power::PowerState;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// Accumulates time spent in each power state, optionally split by the online core count.
+/// Each `record` closes the running segment and credits it to the state it started in.
+pub struct PowerDurations {
+    by_core_count: bool,
+    current: Option<(PowerState, usize, Instant)>,
+    totals: BTreeMap<String, f64>,
+    core_totals: BTreeMap<String, BTreeMap<usize, f64>>,
+}
+
+impl PowerDurations {
+    pub fn new(by_core_count: bool) -> Self {
+        Self {
+            by_core_count,
+            current: None,
+            totals: BTreeMap::new(),
+            core_totals: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, state: PowerState, cores: usize) {
+        self.record_at(state, cores, Instant::now());
+    }
+
+    fn record_at(&mut self, state: PowerState, cores: usize, now: Instant) {
+        if let Some((last_state, last_cores, since)) = self.current {
+            if last_state == state && last_cores == cores {
+                return;
+            }
+            self.credit(
+                last_state,
+                last_cores,
+                now.duration_since(since).as_secs_f64(),
+            );
+        }
+        self.current = Some((state, cores, now));
+    }
+
+    /// Drops the accumulated totals; the running segment restarts from now.
+    pub fn reset(&mut self) {
+        self.totals.clear();
+        self.core_totals.clear();
+        if let Some((_, _, since)) = &mut self.current {
+            *since = Instant::now();
+        }
+    }
+
+    /// Seconds per power state, and per power state and core count when enabled, including
+    /// the segment still running.
+    pub fn totals(
+        &self,
+    ) -> (
+        BTreeMap<String, f64>,
+        BTreeMap<String, BTreeMap<usize, f64>>,
+    ) {
+        self.totals_at(Instant::now())
+    }
+
+    fn totals_at(
+        &self,
+        now: Instant,
+    ) -> (
+        BTreeMap<String, f64>,
+        BTreeMap<String, BTreeMap<usize, f64>>,
+    ) {
+        let mut totals = Self {
+            by_core_count: self.by_core_count,
+            current: None,
+            totals: self.totals.clone(),
+            core_totals: self.core_totals.clone(),
+        };
+        if let Some((state, cores, since)) = self.current {
+            totals.credit(state, cores, now.duration_since(since).as_secs_f64());
+        }
+        (totals.totals, totals.core_totals)
+    }
+
+    fn credit(&mut self, state: PowerState, cores: usize, seconds: f64) {
+        let key = format!("{:?}", state);
+        *self.totals.entry(key.clone()).or_default() += seconds;
+        if self.by_core_count {
+            *self
+                .core_totals
+                .entry(key)
+                .or_default()
+                .entry(cores)
+                .or_default() += seconds;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_accumulates_per_state_and_core_count() {
+        let start = Instant::now();
+        let mut durations = PowerDurations::new(true);
+        durations.record_at(PowerState::AC, 8, start);
+        durations.record_at(PowerState::AC, 8, start + Duration::from_secs(5));
+        durations.record_at(PowerState::Battery, 8, start + Duration::from_secs(10));
+        durations.record_at(PowerState::Battery, 4, start + Duration::from_secs(12));
+
+        let (totals, by_cores) = durations.totals_at(start + Duration::from_secs(15));
+        assert_eq!(totals["AC"], 10.0);
+        assert_eq!(totals["Battery"], 5.0);
+        assert_eq!(by_cores["Battery"][&8], 2.0);
+        assert_eq!(by_cores["Battery"][&4], 3.0);
+
+        durations.reset();
+        let (totals, _) = durations.totals_at(Instant::now());
+        assert!(totals["Battery"] < 1.0);
+        assert!(!totals.contains_key("AC"));
+    }
+}
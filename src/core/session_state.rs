@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What a running observer has changed, kept on disk so the next start can undo it if the
+/// process died without its cleanup (SIGKILL, OOM kill, power loss).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub pid: u32,
+    pub offline_cores: Vec<usize>,    // Cores observer took offline
+    pub original_epp: Option<String>, // EPP found at startup, before observer changed it
+}
+
+impl SessionState {
+    /// Reads the state left by a previous session. None when there is no state file.
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the state as JSON, creating the parent directory and replacing the file
+    /// atomically.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_state_round_trip() {
+        let dir = std::env::temp_dir().join(format!("observer-state-{}", std::process::id()));
+        let path = dir.join("lib/state.json");
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(SessionState::load(&path).unwrap(), None);
+
+        let state = SessionState {
+            pid: 42,
+            offline_cores: vec![2, 3],
+            original_epp: Some("balance_performance".to_string()),
+        };
+        state.write_to(&path).unwrap();
+        assert_eq!(SessionState::load(&path).unwrap(), Some(state));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(SessionState::load(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
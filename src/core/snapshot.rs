@@ -0,0 +1,38 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Point-in-time view of the manager's state, written to the status file each iteration.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CoreSnapshot {
+    pub current_cores: usize,
+    pub p_cores: usize, // P-cores among the selected cores (0 without topology)
+    pub e_cores: usize, // E-cores among the selected cores
+    pub avg_load: f32,
+    pub p_core_load: f32, // Latest average usage of online P-cores
+    pub e_core_load: f32, // Latest average usage of online E-cores
+    pub on_battery: bool,
+    pub power_state: Option<String>, // Last power state seen (AC, Battery, ...)
+    pub epp: Option<String>,         // EPP of the applied profile
+    pub per_core_usage: Vec<f32>,
+    pub smoothed_core_usage: Vec<f32>, // Per-core EWMA (per_core_smoothing_alpha)
+    pub core_changes: u64,             // Core count changes since startup
+    pub hotplug_counts: BTreeMap<usize, u64>, // Online/offline toggles per CPU since startup
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub power_state_seconds: BTreeMap<String, f64>, // Time per power state, if tracked
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub power_state_core_seconds: BTreeMap<String, BTreeMap<usize, f64>>, // Split by core count
+}
+
+impl CoreSnapshot {
+    /// Writes the snapshot as JSON, replacing the file atomically so readers never see a
+    /// partial write.
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(tmp_path, path)
+    }
+}
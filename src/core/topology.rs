@@ -1,13 +1,10 @@
-use log::{debug, warn};
+use crate::config::OfflinePriority;
+use crate::system::sysfs::{LinuxSysfs, SysfsProvider};
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 
-#[cfg(target_os = "linux")]
-use std::{fs, path::Path};
-
-#[cfg(target_os = "linux")]
-use std::collections::HashMap;
-
-#[cfg(target_os = "linux")]
-use log::info;
+pub const CPU_SYSFS_PATH: &str = "/sys/devices/system/cpu";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CoreType {
@@ -21,6 +18,38 @@ pub struct CoreInfo {
     pub id: usize,
     pub sibling_id: usize, // ID of the other thread in the SMT pair
     pub core_type: CoreType,
+    pub cluster_id: usize, // Lowest CPU ID sharing this core's last-level cache
+    pub numa_node: usize,
+}
+
+/// Knobs controlling which logical CPUs `get_cores_to_enable` picks.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionPolicy {
+    pub group_by_cache: bool, // Fill whole LLC groups before spilling into another
+    pub group_by_numa: bool,  // Fill whole NUMA nodes before spilling into another
+    pub offline_priority: OfflinePriority,
+    pub keep_numa_representatives: bool, // Never leave a NUMA node without an online CPU
+    pub smt_siblings_first: bool,        // Offline every second SMT thread before any whole core
+}
+
+impl Default for SelectionPolicy {
+    fn default() -> Self {
+        Self {
+            group_by_cache: false,
+            group_by_numa: false,
+            offline_priority: OfflinePriority::Efficiency,
+            keep_numa_representatives: true,
+            smt_siblings_first: false,
+        }
+    }
+}
+
+// Raw per-CPU facts gathered in the first sysfs pass
+struct CpuDetails {
+    sibling_id: Option<usize>,
+    max_freq: Option<usize>,
+    capacity: Option<usize>, // Relative performance from cpu_capacity (ARM), max 1024
+    cluster_id: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +57,8 @@ pub struct CPUTopology {
     pub cores: Vec<CoreInfo>, // Info for each physical core (one entry per pair)
     pub num_p_cores: usize,   // Count of physical Performance cores
     pub num_e_cores: usize,   // Count of physical Efficiency cores
+    pub cpu_ids: Vec<usize>,  // All logical CPU IDs found, sorted (may be sparse)
+    pub protected_cores: HashSet<usize>, // Logical CPUs that are never taken offline
 }
 
 impl Default for CPUTopology {
@@ -36,31 +67,174 @@ impl Default for CPUTopology {
     }
 }
 
+/// Parses a kernel cpulist such as `0-3,8,10-11` into individual CPU IDs.
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut ids = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    ids.extend(start..=end);
+                }
+            }
+            None => {
+                if let Ok(id) = part.parse() {
+                    ids.push(id);
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Maps each CPU to its NUMA node from `nodeN/cpulist` in the sibling `node` directory.
+/// CPUs on machines without NUMA information all land on node 0.
+fn read_numa_nodes(sysfs: &dyn SysfsProvider, cpu_path: &Path) -> HashMap<usize, usize> {
+    let mut node_of = HashMap::new();
+    let Some(node_path) = cpu_path.parent().map(|p| p.join("node")) else {
+        return node_of;
+    };
+    let Ok(entries) = sysfs.list(&node_path) else {
+        debug!("No NUMA information at {}", node_path.display());
+        return node_of;
+    };
+
+    for name in entries {
+        let Some(node) = name
+            .strip_prefix("node")
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        if let Ok(list) = sysfs.read(&node_path.join(&name).join("cpulist")) {
+            for cpu in parse_cpu_list(&list) {
+                node_of.insert(cpu, node);
+            }
+        }
+    }
+    node_of
+}
+
+/// Lists the logical CPU IDs that have a `cpuN` directory, without assuming they are contiguous.
+pub fn enumerate_cpu_ids(sysfs: &dyn SysfsProvider, cpu_path: &Path) -> Vec<usize> {
+    let mut ids: Vec<usize> = match sysfs.list(cpu_path) {
+        Ok(entries) => entries
+            .iter()
+            .filter_map(|name| name.strip_prefix("cpu").and_then(|n| n.parse().ok()))
+            .collect(),
+        Err(e) => {
+            debug!("Could not read {}: {}", cpu_path.display(), e);
+            Vec::new()
+        }
+    };
+    ids.sort_unstable();
+    ids
+}
+
+/// CPUs without an `online` file can't be hot-unplugged (usually just the boot CPU), so
+/// they count as protected.
+fn read_protected_cores(
+    sysfs: &dyn SysfsProvider,
+    cpu_path: &Path,
+    cpu_ids: &[usize],
+) -> HashSet<usize> {
+    cpu_ids
+        .iter()
+        .copied()
+        .filter(|id| !sysfs.exists(&cpu_path.join(format!("cpu{}/online", id))))
+        .collect()
+}
+
+/// Drops CPUs the kernel does not list in `present`, such as cores disabled in firmware
+/// that still leave a `cpuN` directory behind. Without a `present` file all CPUs are kept.
+pub(crate) fn filter_present(
+    sysfs: &dyn SysfsProvider,
+    cpu_path: &Path,
+    cpu_ids: Vec<usize>,
+) -> Vec<usize> {
+    let Ok(list) = sysfs.read(&cpu_path.join("present")) else {
+        return cpu_ids;
+    };
+    let present: HashSet<usize> = parse_cpu_list(&list).into_iter().collect();
+    if present.is_empty() {
+        return cpu_ids;
+    }
+    cpu_ids
+        .into_iter()
+        .filter(|id| {
+            let keep = present.contains(id);
+            if !keep {
+                debug!(
+                    "CPU {} is not present (disabled in firmware?), ignoring it",
+                    id
+                );
+            }
+            keep
+        })
+        .collect()
+}
+
+/// Reads the kernel's `online` mask, listing every online CPU in a single read. None when
+/// the file is missing or unreadable.
+pub fn read_online_mask(sysfs: &dyn SysfsProvider, cpu_path: &Path) -> Option<HashSet<usize>> {
+    let list = sysfs.read(&cpu_path.join("online")).ok()?;
+    Some(parse_cpu_list(&list).into_iter().collect())
+}
+
+/// The lowest cpu_capacity when cores should be classified by capacity instead of max
+/// frequency: frequencies are missing or all equal, while capacities differ. None keeps the
+/// frequency heuristic.
+fn capacity_fallback(core_details: &HashMap<usize, CpuDetails>) -> Option<usize> {
+    let freqs: HashSet<usize> = core_details.values().filter_map(|d| d.max_freq).collect();
+    if freqs.len() > 1 {
+        return None;
+    }
+    let capacities: HashSet<usize> = core_details.values().filter_map(|d| d.capacity).collect();
+    if capacities.len() > 1 {
+        capacities.into_iter().min()
+    } else {
+        None
+    }
+}
+
+/// Maps a sysinfo CPU entry to its sysfs `cpuN` index. sysinfo names Linux CPUs after
+/// their `/proc/stat` line ("cpu3"), which keeps the kernel's numbering even when offline
+/// CPUs are left out of the list; the list position is only a fallback for other names.
+pub fn sysfs_cpu_index(name: &str, position: usize) -> usize {
+    name.strip_prefix("cpu")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(position)
+}
+
 impl CPUTopology {
     #[cfg(target_os = "linux")]
     pub fn new() -> Self {
-        let cpu_path = Path::new("/sys/devices/system/cpu");
-        let mut core_details = HashMap::new(); // Map core_id -> (Option<sibling_id>, Option<max_freq_khz>)
+        Self::from_sysfs(Path::new(CPU_SYSFS_PATH))
+    }
+
+    pub fn from_sysfs(cpu_path: &Path) -> Self {
+        Self::from_provider(&LinuxSysfs, cpu_path)
+    }
+
+    /// Reads the topology under `cpu_path` through `sysfs`.
+    pub fn from_provider(sysfs: &dyn SysfsProvider, cpu_path: &Path) -> Self {
+        let cpu_ids = filter_present(sysfs, cpu_path, enumerate_cpu_ids(sysfs, cpu_path));
+        let numa_nodes = read_numa_nodes(sysfs, cpu_path);
+        let protected_cores = read_protected_cores(sysfs, cpu_path, &cpu_ids);
+        let mut core_details = HashMap::new(); // Map core_id -> CpuDetails
         let mut max_freq_overall = 0;
 
         // First pass: Discover cores, siblings, and max frequencies
-        for i in 0.. {
+        for &i in &cpu_ids {
             let core_dir = cpu_path.join(format!("cpu{}", i));
-            if !core_dir.exists() {
-                break; // Assume we found all cores
-            }
 
             let mut sibling_id = Some(i); // Default sibling to self if not found
             let mut max_freq = None;
 
             // Read siblings
             let siblings_path = core_dir.join("topology/thread_siblings_list");
-            if let Ok(siblings_str) = fs::read_to_string(siblings_path) {
-                let siblings: Vec<usize> = siblings_str
-                    .trim()
-                    .split(',')
-                    .filter_map(|s| s.parse().ok())
-                    .collect();
+            if let Ok(siblings_str) = sysfs.read(&siblings_path) {
+                let siblings = parse_cpu_list(&siblings_str);
                 // Find the sibling that isn't the current core 'i'
                 if let Some(other_sibling) = siblings.iter().find(|&&s| s != i) {
                     sibling_id = Some(*other_sibling);
@@ -72,7 +246,7 @@ impl CPUTopology {
 
             // Read max frequency
             let freq_path = core_dir.join("cpufreq/scaling_max_freq");
-            if let Ok(freq_str) = fs::read_to_string(freq_path) {
+            if let Ok(freq_str) = sysfs.read(&freq_path) {
                 if let Ok(freq_khz) = freq_str.trim().parse::<usize>() {
                     max_freq = Some(freq_khz);
                     if freq_khz > max_freq_overall {
@@ -81,7 +255,28 @@ impl CPUTopology {
                 }
             }
 
-            core_details.insert(i, (sibling_id, max_freq));
+            let capacity = sysfs
+                .read(&core_dir.join("cpu_capacity"))
+                .ok()
+                .and_then(|capacity| capacity.trim().parse().ok());
+
+            // Read last-level cache sharing, grouping by the lowest CPU in the list
+            let cache_path = core_dir.join("cache/index3/shared_cpu_list");
+            let cluster_id = sysfs
+                .read(&cache_path)
+                .ok()
+                .and_then(|list| parse_cpu_list(&list).into_iter().min())
+                .unwrap_or(0);
+
+            core_details.insert(
+                i,
+                CpuDetails {
+                    sibling_id,
+                    max_freq,
+                    capacity,
+                    cluster_id,
+                },
+            );
         }
 
         if core_details.is_empty() {
@@ -90,6 +285,8 @@ impl CPUTopology {
                 cores: Vec::new(),
                 num_p_cores: 0,
                 num_e_cores: 0,
+                cpu_ids,
+                protected_cores,
             };
         }
 
@@ -100,8 +297,18 @@ impl CPUTopology {
             max_freq_overall, freq_threshold
         );
 
+        // ARM big.LITTLE parts can share one frequency range, so fall back to cpu_capacity
+        // when frequencies can't tell the cores apart
+        let lowest_capacity = capacity_fallback(&core_details);
+        if let Some(lowest) = lowest_capacity {
+            debug!(
+                "Max frequencies don't differ, classifying cores with cpu_capacity {} as E-cores",
+                lowest
+            );
+        }
+
         let mut final_cores = Vec::new();
-        let mut processed_ids = std::collections::HashSet::new(); // Keep track of processed core IDs
+        let mut processed_ids = HashSet::new(); // Keep track of processed core IDs
         let mut p_core_count = 0;
         let mut e_core_count = 0;
 
@@ -114,19 +321,26 @@ impl CPUTopology {
                 continue; // Already processed as part of a pair
             }
 
-            if let Some((sibling_opt, freq_opt)) = core_details.get(&core_id) {
-                let sibling_id = sibling_opt.unwrap_or(core_id); // Default to self if None
+            if let Some(details) = core_details.get(&core_id) {
+                let sibling_id = details.sibling_id.unwrap_or(core_id); // Default to self if None
 
-                let core_type = match freq_opt {
-                    Some(freq) => {
+                let core_type = match (lowest_capacity, details.capacity, details.max_freq) {
+                    (Some(lowest), Some(capacity), _) => {
+                        if capacity == lowest {
+                            CoreType::Efficiency
+                        } else {
+                            CoreType::Performance
+                        }
+                    }
+                    (_, _, Some(freq)) => {
                         // Compare dereferenced freq with freq_threshold
-                        if max_freq_overall > 0 && *freq < freq_threshold {
+                        if max_freq_overall > 0 && freq < freq_threshold {
                             CoreType::Efficiency
                         } else {
                             CoreType::Performance
                         }
                     }
-                    None => {
+                    _ => {
                         warn!(
                             "Could not determine max frequency for CPU {}, classifying as Unknown.",
                             core_id
@@ -139,6 +353,8 @@ impl CPUTopology {
                     id: core_id,
                     sibling_id,
                     core_type,
+                    cluster_id: details.cluster_id,
+                    numa_node: numa_nodes.get(&core_id).copied().unwrap_or(0),
                 };
                 final_cores.push(core_info);
 
@@ -167,8 +383,8 @@ impl CPUTopology {
         }
         for core in &final_cores {
             debug!(
-                "  Core {}: Type={:?}, Sibling={}",
-                core.id, core.core_type, core.sibling_id
+                "  Core {}: Type={:?}, Sibling={}, Cluster={}, Node={}",
+                core.id, core.core_type, core.sibling_id, core.cluster_id, core.numa_node
             );
         }
 
@@ -176,6 +392,8 @@ impl CPUTopology {
             cores: final_cores,
             num_p_cores: p_core_count,
             num_e_cores: e_core_count,
+            cpu_ids,
+            protected_cores,
         }
     }
 
@@ -186,6 +404,487 @@ impl CPUTopology {
             cores: Vec::new(), // Return empty topology on non-Linux
             num_p_cores: 0,
             num_e_cores: 0,
+            cpu_ids: Vec::new(),
+            protected_cores: HashSet::new(),
+        }
+    }
+
+    /// Re-reads which CPUs can't be hot-unplugged and adds the `configured` ones on top.
+    pub fn set_protected_cores(
+        &mut self,
+        sysfs: &dyn SysfsProvider,
+        cpu_path: &Path,
+        configured: &[usize],
+    ) {
+        self.protected_cores = read_protected_cores(sysfs, cpu_path, &self.cpu_ids);
+        self.protected_cores.extend(configured);
+        let mut protected: Vec<usize> = self.protected_cores.iter().copied().collect();
+        protected.sort_unstable();
+        debug!("Protected CPUs: {:?}", protected);
+    }
+
+    /// Logical CPU IDs in the order they should be brought online: cores with a protected
+    /// CPU first, then the kept core type, Unknown, and the type offlined first, each
+    /// followed by its SMT sibling.
+    fn enable_order(&self, offline_priority: OfflinePriority) -> Vec<usize> {
+        let type_rank = |core_type: CoreType| match (core_type, offline_priority) {
+            (CoreType::Unknown, _) => 1,
+            (CoreType::Performance, OfflinePriority::Efficiency)
+            | (CoreType::Efficiency, OfflinePriority::Performance) => 0,
+            _ => 2,
+        };
+
+        let mut physical: Vec<&CoreInfo> = self.cores.iter().collect();
+        physical.sort_by_key(|core| {
+            let is_protected = self.protected_cores.contains(&core.id)
+                || self.protected_cores.contains(&core.sibling_id);
+            (!is_protected, type_rank(core.core_type), core.id)
+        });
+
+        let mut order = Vec::with_capacity(self.cpu_ids.len());
+        for core in physical {
+            order.push(core.id);
+            if core.sibling_id != core.id {
+                order.push(core.sibling_id);
+            }
+        }
+        // CPUs the topology pass couldn't place still get enabled, lowest ID first
+        for id in &self.cpu_ids {
+            if !order.contains(id) {
+                order.push(*id);
+            }
+        }
+        order
+    }
+
+    /// Core type of a logical CPU, looking through SMT siblings.
+    pub fn core_type_of(&self, cpu: usize) -> Option<CoreType> {
+        self.cores
+            .iter()
+            .find(|core| core.id == cpu || core.sibling_id == cpu)
+            .map(|core| core.core_type)
+    }
+
+    /// Reorders `order` so each group (by `key`) is filled completely before the next one
+    /// starts. Groups are visited in the order their first CPU appears.
+    fn group_order(&self, order: Vec<usize>, key: impl Fn(&CoreInfo) -> usize) -> Vec<usize> {
+        let group_of: HashMap<usize, usize> = self
+            .cores
+            .iter()
+            .flat_map(|core| [(core.id, key(core)), (core.sibling_id, key(core))])
+            .collect();
+
+        let mut groups: Vec<(Option<usize>, Vec<usize>)> = Vec::new();
+        for id in order {
+            let group = group_of.get(&id).copied();
+            match groups.iter_mut().find(|(g, _)| *g == group) {
+                Some((_, members)) => members.push(id),
+                None => groups.push((group, vec![id])),
+            }
+        }
+        groups
+            .into_iter()
+            .flat_map(|(_, members)| members)
+            .collect()
+    }
+
+    /// Returns the logical CPU IDs that should be online to run `target_cores` cores.
+    pub fn get_cores_to_enable(&self, target_cores: usize, policy: &SelectionPolicy) -> Vec<usize> {
+        let mut order = self.enable_order(policy.offline_priority);
+        if policy.group_by_cache {
+            order = self.group_order(order, |core| core.cluster_id);
+        }
+        // NUMA grouping goes last so it is the outermost: whole nodes, then LLC groups within
+        if policy.group_by_numa {
+            order = self.group_order(order, |core| core.numa_node);
+        }
+        if policy.smt_siblings_first {
+            order = self.siblings_last(order);
+        }
+        // Protected CPUs stay online whatever the target, so they go first and count towards it
+        let (mut protected, rest): (Vec<usize>, Vec<usize>) = order
+            .into_iter()
+            .partition(|cpu| self.protected_cores.contains(cpu));
+        protected.extend(rest);
+        let order = protected;
+        if !policy.keep_numa_representatives {
+            return order.into_iter().take(target_cores).collect();
         }
+        self.with_numa_representatives(&order, target_cores)
+    }
+
+    /// Moves the second thread of every SMT pair (the higher ID) behind all first threads,
+    /// keeping the relative order within both halves. Taking a prefix of the result keeps
+    /// one thread per core online before any core gets its second thread back.
+    fn siblings_last(&self, order: Vec<usize>) -> Vec<usize> {
+        let second_threads: HashSet<usize> = self
+            .cores
+            .iter()
+            .filter(|core| core.sibling_id != core.id)
+            .map(|core| core.id.max(core.sibling_id))
+            .collect();
+        let (mut first, second): (Vec<usize>, Vec<usize>) = order
+            .into_iter()
+            .partition(|cpu| !second_threads.contains(cpu));
+        first.extend(second);
+        first
+    }
+
+    /// Counts logical CPUs in `cpus` by core type, as (P-cores, E-cores, unknown).
+    pub fn type_breakdown(&self, cpus: &[usize]) -> (usize, usize, usize) {
+        cpus.iter()
+            .fold((0, 0, 0), |(p, e, u), &cpu| match self.core_type_of(cpu) {
+                Some(CoreType::Performance) => (p + 1, e, u),
+                Some(CoreType::Efficiency) => (p, e + 1, u),
+                _ => (p, e, u + 1),
+            })
+    }
+
+    fn numa_node_of(&self, cpu: usize) -> Option<usize> {
+        self.cores
+            .iter()
+            .find(|core| core.id == cpu || core.sibling_id == cpu)
+            .map(|core| core.numa_node)
+    }
+
+    /// Takes the first `target_cores` of `order`, then swaps in the first CPU of every NUMA
+    /// node left without one. Swapped-out CPUs come from nodes that still keep another CPU,
+    /// so the count only grows when there are more nodes than `target_cores`.
+    fn with_numa_representatives(&self, order: &[usize], target_cores: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = order.iter().take(target_cores).copied().collect();
+
+        let mut nodes: Vec<(usize, usize)> = Vec::new(); // (node, first CPU in order)
+        for &cpu in order {
+            if let Some(node) = self.numa_node_of(cpu) {
+                if !nodes.iter().any(|(n, _)| *n == node) {
+                    nodes.push((node, cpu));
+                }
+            }
+        }
+        if nodes.len() < 2 {
+            return selected;
+        }
+
+        for (node, representative) in nodes {
+            if selected
+                .iter()
+                .any(|&cpu| self.numa_node_of(cpu) == Some(node))
+            {
+                continue;
+            }
+            debug!(
+                "Keeping CPU {} online so NUMA node {} isn't left empty",
+                representative, node
+            );
+            // Never swap out a protected CPU
+            let removable = selected.iter().rposition(|&cpu| {
+                let cpu_node = self.numa_node_of(cpu);
+                !self.protected_cores.contains(&cpu)
+                    && selected
+                        .iter()
+                        .filter(|&&other| self.numa_node_of(other) == cpu_node)
+                        .count()
+                        > 1
+            });
+            if let Some(pos) = removable {
+                selected.remove(pos);
+            }
+            selected.push(representative);
+        }
+        selected
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::system::sysfs::MockSysfs;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Builds a throwaway `/sys/devices/system/cpu` lookalike and returns its `cpu` directory.
+    /// Each entry is `(cpu_id, siblings_list, max_freq_khz)`.
+    pub(crate) fn fake_cpu_sysfs(name: &str, cpus: &[(usize, &str, usize)]) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("observer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        let root = base.join("cpu");
+        for (id, siblings, freq) in cpus {
+            let dir = root.join(format!("cpu{}", id));
+            fs::create_dir_all(dir.join("topology")).unwrap();
+            fs::create_dir_all(dir.join("cpufreq")).unwrap();
+            fs::write(dir.join("topology/thread_siblings_list"), siblings).unwrap();
+            fs::write(dir.join("cpufreq/scaling_max_freq"), freq.to_string()).unwrap();
+            if *id != 0 {
+                fs::write(dir.join("online"), "1").unwrap();
+            }
+        }
+        root
+    }
+
+    pub(crate) fn remove_fake_sysfs(root: &Path) {
+        fs::remove_dir_all(root.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0-2,5,7-8\n"), vec![0, 1, 2, 5, 7, 8]);
+        assert_eq!(parse_cpu_list("3"), vec![3]);
+        assert!(parse_cpu_list("").is_empty());
+    }
+
+    #[test]
+    fn test_sparse_cpu_numbering() {
+        let root = fake_cpu_sysfs(
+            "sparse-topology",
+            &[
+                (0, "0-1", 4000000),
+                (1, "0-1", 4000000),
+                (4, "4-5", 4000000),
+                (5, "4-5", 4000000),
+            ],
+        );
+        let topology = CPUTopology::from_sysfs(&root);
+
+        assert_eq!(topology.cpu_ids, vec![0, 1, 4, 5]);
+        assert_eq!(topology.cores.len(), 2);
+        assert_eq!(topology.cores[1].id, 4);
+        assert_eq!(topology.cores[1].sibling_id, 5);
+        let policy = SelectionPolicy::default();
+        assert_eq!(topology.get_cores_to_enable(3, &policy), vec![0, 1, 4]);
+        assert_eq!(topology.get_cores_to_enable(10, &policy), vec![0, 1, 4, 5]);
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_smt_siblings_first() {
+        let root = fake_cpu_sysfs(
+            "smt-first",
+            &[
+                (0, "0-1", 4000000),
+                (1, "0-1", 4000000),
+                (2, "2-3", 4000000),
+                (3, "2-3", 4000000),
+                (4, "4-5", 4000000),
+                (5, "4-5", 4000000),
+            ],
+        );
+        let topology = CPUTopology::from_sysfs(&root);
+        let policy = SelectionPolicy::default();
+        assert_eq!(topology.get_cores_to_enable(4, &policy), vec![0, 1, 2, 3]);
+
+        let smt_first = SelectionPolicy {
+            smt_siblings_first: true,
+            ..policy
+        };
+        assert_eq!(
+            topology.get_cores_to_enable(6, &smt_first),
+            vec![0, 2, 4, 1, 3, 5]
+        );
+        // Going from 6 to 4 drops two second threads, not a whole core
+        assert_eq!(
+            topology.get_cores_to_enable(4, &smt_first),
+            vec![0, 2, 4, 1]
+        );
+        assert_eq!(topology.get_cores_to_enable(2, &smt_first), vec![0, 2]);
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_protected_cores() {
+        let root = fake_cpu_sysfs(
+            "protected-cores",
+            &[
+                (0, "0", 4000000),
+                (1, "1", 4000000),
+                (2, "2", 4000000),
+                (3, "3", 4000000),
+            ],
+        );
+        fs::remove_file(root.join("cpu2/online")).unwrap();
+        let mut topology = CPUTopology::from_sysfs(&root);
+        assert_eq!(topology.protected_cores, HashSet::from([0, 2]));
+
+        // Configured cores add to the ones without an online file and go first in the order
+        topology.set_protected_cores(&LinuxSysfs, &root, &[3]);
+        assert_eq!(topology.protected_cores, HashSet::from([0, 2, 3]));
+        let policy = SelectionPolicy::default();
+        assert_eq!(topology.get_cores_to_enable(3, &policy), vec![0, 2, 3]);
+        assert_eq!(topology.get_cores_to_enable(4, &policy), vec![0, 2, 3, 1]);
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_read_online_mask() {
+        let root = fake_cpu_sysfs("online-mask", &[(0, "0", 4000000), (1, "1", 4000000)]);
+        assert!(read_online_mask(&LinuxSysfs, &root).is_none());
+        fs::write(root.join("online"), "0,2-3\n").unwrap();
+        assert_eq!(
+            read_online_mask(&LinuxSysfs, &root),
+            Some(HashSet::from([0, 2, 3]))
+        );
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_non_present_cores_are_ignored() {
+        let root = fake_cpu_sysfs(
+            "present-gap",
+            &[
+                (0, "0-1", 4000000),
+                (1, "0-1", 4000000),
+                (2, "2", 2000000),
+                (3, "3", 2000000),
+                (4, "4", 2000000),
+            ],
+        );
+        // CPU 3 is an E-core disabled in firmware that still has a cpu3 directory
+        fs::write(root.join("present"), "0-2,4\n").unwrap();
+        fs::remove_file(root.join("cpu3/online")).unwrap();
+        let topology = CPUTopology::from_sysfs(&root);
+
+        assert_eq!(topology.cpu_ids, vec![0, 1, 2, 4]);
+        assert_eq!(topology.num_p_cores, 1);
+        assert_eq!(topology.num_e_cores, 2);
+        assert!(topology.core_type_of(3).is_none());
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_enable_order_prefers_p_cores() {
+        let root = fake_cpu_sysfs(
+            "hybrid-topology",
+            &[
+                (0, "0", 4000000),
+                (2, "2", 2000000),
+                (3, "3", 2000000),
+                (6, "6", 4000000),
+            ],
+        );
+        let topology = CPUTopology::from_sysfs(&root);
+
+        assert_eq!(topology.num_p_cores, 2);
+        assert_eq!(topology.num_e_cores, 2);
+        assert_eq!(
+            topology.get_cores_to_enable(3, &SelectionPolicy::default()),
+            vec![0, 6, 2]
+        );
+        let keep_e_cores = SelectionPolicy {
+            offline_priority: OfflinePriority::Performance,
+            ..SelectionPolicy::default()
+        };
+        assert_eq!(
+            topology.get_cores_to_enable(3, &keep_e_cores),
+            vec![0, 2, 3]
+        );
+        assert_eq!(topology.type_breakdown(&[0, 2, 3]), (1, 2, 0));
+        assert_eq!(topology.type_breakdown(&[0, 6, 9]), (2, 0, 1));
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_arm_capacity_fallback() {
+        // 4+4 big.LITTLE with one shared frequency range; only cpu_capacity differs
+        let sysfs = MockSysfs::new();
+        let cpu_path = Path::new("/cpu");
+        for cpu in 0..8 {
+            let dir = cpu_path.join(format!("cpu{}", cpu));
+            sysfs.insert(dir.join("topology/thread_siblings_list"), &cpu.to_string());
+            sysfs.insert(dir.join("cpufreq/scaling_max_freq"), "1800000");
+            sysfs.insert(
+                dir.join("cpu_capacity"),
+                if cpu < 4 { "446" } else { "1024" },
+            );
+        }
+        let topology = CPUTopology::from_provider(&sysfs, cpu_path);
+        assert_eq!(topology.num_e_cores, 4);
+        assert_eq!(topology.num_p_cores, 4);
+        assert_eq!(topology.core_type_of(1), Some(CoreType::Efficiency));
+        assert_eq!(topology.core_type_of(5), Some(CoreType::Performance));
+
+        // Differing frequencies stay the primary signal
+        sysfs.insert(cpu_path.join("cpu0/cpufreq/scaling_max_freq"), "900000");
+        let topology = CPUTopology::from_provider(&sysfs, cpu_path);
+        assert_eq!(topology.num_e_cores, 1);
+        assert_eq!(topology.core_type_of(5), Some(CoreType::Performance));
+    }
+
+    #[test]
+    fn test_group_by_cache_fills_clusters() {
+        let cpus: Vec<(usize, &str, usize)> = (0..8).map(|i| (i, "", 4000000)).collect();
+        let root = fake_cpu_sysfs("cache-groups", &cpus);
+        for i in 0..8 {
+            let cache_dir = root.join(format!("cpu{}/cache/index3", i));
+            fs::create_dir_all(&cache_dir).unwrap();
+            let shared = if [0, 1, 4, 5].contains(&i) {
+                "0-1,4-5"
+            } else {
+                "2-3,6-7"
+            };
+            fs::write(cache_dir.join("shared_cpu_list"), shared).unwrap();
+        }
+        let topology = CPUTopology::from_sysfs(&root);
+
+        assert_eq!(topology.cores[2].cluster_id, 2);
+        assert_eq!(
+            topology.get_cores_to_enable(4, &SelectionPolicy::default()),
+            vec![0, 1, 2, 3]
+        );
+        let policy = SelectionPolicy {
+            group_by_cache: true,
+            ..SelectionPolicy::default()
+        };
+        assert_eq!(topology.get_cores_to_enable(4, &policy), vec![0, 1, 4, 5]);
+        assert_eq!(
+            topology.get_cores_to_enable(6, &policy),
+            vec![0, 1, 4, 5, 2, 3]
+        );
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_group_by_numa_fills_nodes() {
+        let cpus: Vec<(usize, &str, usize)> = (0..8).map(|i| (i, "", 4000000)).collect();
+        let root = fake_cpu_sysfs("numa-nodes", &cpus);
+        let node_path = root.parent().unwrap().join("node");
+        for (node, list) in [(0, "0,2,4,6"), (1, "1,3,5,7")] {
+            fs::create_dir_all(node_path.join(format!("node{}", node))).unwrap();
+            fs::write(node_path.join(format!("node{}/cpulist", node)), list).unwrap();
+        }
+        let topology = CPUTopology::from_sysfs(&root);
+
+        assert_eq!(topology.cores[3].numa_node, 1);
+        let policy = SelectionPolicy {
+            group_by_numa: true,
+            ..SelectionPolicy::default()
+        };
+        assert_eq!(
+            topology.get_cores_to_enable(5, &policy),
+            vec![0, 2, 4, 6, 1]
+        );
+        // Node 1 keeps one CPU online even when node 0 alone could cover the target
+        assert_eq!(topology.get_cores_to_enable(4, &policy), vec![0, 2, 4, 1]);
+        assert_eq!(topology.get_cores_to_enable(1, &policy), vec![0, 1]);
+
+        let parking = SelectionPolicy {
+            keep_numa_representatives: false,
+            ..policy
+        };
+        assert_eq!(topology.get_cores_to_enable(4, &parking), vec![0, 2, 4, 6]);
+
+        remove_fake_sysfs(&root);
+    }
+
+    #[test]
+    fn test_sysfs_cpu_index_uses_name() {
+        // sysinfo skipped offline cpu1, so "cpu2" sits at position 1
+        assert_eq!(sysfs_cpu_index("cpu2", 1), 2);
+        assert_eq!(sysfs_cpu_index("cpu0", 0), 0);
+        assert_eq!(sysfs_cpu_index("CPU 3", 3), 3);
     }
 }
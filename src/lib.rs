@@ -4,4 +4,5 @@ pub mod system;
 pub mod utils;
 
 pub use config::Settings;
-pub use core::{topology::CPUTopology, CoreManager};
+pub use core::epp::{list_epp_policies, set_epp_for_policy};
+pub use core::{error::CoreError, snapshot::CoreSnapshot, topology::CPUTopology, CoreManager};
@@ -1,21 +1,496 @@
+use clap::Parser;
 use log::{debug, error, info, warn};
 use std::error::Error;
+use std::path::Path;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use sysinfo::System;
 
-use crate::utils::logging;
+use crate::cli::Command;
+use crate::config::AssumedPowerState;
+#[cfg(unix)]
+use crate::utils::status_socket::{SharedSnapshot, StatusSocket};
+use crate::utils::{logging, signals, systemd};
 
+mod cli;
 mod config;
 mod core;
 mod system;
 mod utils;
 
+fn print_cores() -> Result<(), Box<dyn Error>> {
+    let mut sys = System::new();
+    sys.refresh_cpu_usage();
+    thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_cpu_usage();
+
+    println!("{:<6} {:>8}  STATE", "CPU", "USAGE");
+    for (position, cpu) in sys.cpus().iter().enumerate() {
+        let i = crate::core::topology::sysfs_cpu_index(cpu.name(), position);
+        let state = match crate::system::cpu::read_cpu_online_state(i) {
+            Ok(true) => "online",
+            Ok(false) => "offline",
+            Err(_) => "unknown",
+        };
+        println!("{:<6} {:>7.1}%  {}", i, cpu.cpu_usage(), state);
+    }
+    Ok(())
+}
+
+fn print_what_if(
+    args: &cli::Args,
+    settings: &crate::config::Settings,
+) -> Result<(), Box<dyn Error>> {
+    let load = args.load.unwrap_or_default();
+    let total_cores = num_cpus::get();
+    let current_cores = args.current_cores.unwrap_or(total_cores).min(total_cores);
+    let decision = crate::core::manager::simulate_decision(
+        settings,
+        total_cores,
+        current_cores,
+        load,
+        args.on_battery,
+        args.charge,
+    );
+
+    println!(
+        "Power: {}, load: {:.1}%, cores: {} of {} online",
+        if args.on_battery { "battery" } else { "AC" },
+        load,
+        current_cores,
+        total_cores
+    );
+    println!(
+        "Target: {} cores (bound by {}; percentage limit {}, min_cores {})",
+        decision.target_cores,
+        decision.bound,
+        decision.percentage_limit,
+        settings.min_cores.resolve(total_cores)
+    );
+    Ok(())
+}
+
+/// Prints the status file written by the running service.
+fn print_status(settings: &crate::config::Settings) -> Result<(), Box<dyn Error>> {
+    let status_file = settings
+        .status_file
+        .as_ref()
+        .ok_or("status_file is not set in the configuration")?;
+    let status: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(status_file)?)?;
+
+    println!(
+        "Cores online: {} ({} P, {} E)",
+        status["current_cores"], status["p_cores"], status["e_cores"]
+    );
+    println!(
+        "Average load: {:.1}% (P-cores {:.1}%, E-cores {:.1}%)",
+        status["avg_load"].as_f64().unwrap_or(0.0),
+        status["p_core_load"].as_f64().unwrap_or(0.0),
+        status["e_core_load"].as_f64().unwrap_or(0.0)
+    );
+    println!("On battery:   {}", status["on_battery"]);
+
+    if let Some(seconds) = status["power_state_seconds"].as_object() {
+        println!("\n{:<10} {:>10}", "POWER", "TIME");
+        for (state, secs) in seconds {
+            let secs = secs.as_f64().unwrap_or(0.0);
+            println!("{:<10} {:>9.0}s", state, secs);
+            if let Some(by_cores) = status["power_state_core_seconds"][state].as_object() {
+                for (cores, secs) in by_cores {
+                    let secs = secs.as_f64().unwrap_or(0.0);
+                    println!("  {:>3} cores {:>8.0}s", cores, secs);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `observer info`: prints what observer detects and the settings it would run with. Only
+/// reads, so it works without root; the permissions section lists what would need it.
+fn print_info(settings: &crate::config::Settings) -> Result<(), Box<dyn Error>> {
+    let config_files = crate::config::config_files_in_use();
+    if config_files.is_empty() {
+        println!("Config files: none found, using defaults");
+    } else {
+        println!("Config files: {}", config_files.join(", "));
+    }
+
+    let mut topology = crate::core::topology::CPUTopology::default();
+    topology.set_protected_cores(
+        &crate::system::sysfs::LinuxSysfs,
+        Path::new(crate::core::topology::CPU_SYSFS_PATH),
+        &settings.protected_cores,
+    );
+    println!(
+        "Topology:     {} physical cores ({} P-cores, {} E-cores), {} logical CPUs",
+        topology.cores.len(),
+        topology.num_p_cores,
+        topology.num_e_cores,
+        topology.cpu_ids.len()
+    );
+    println!(
+        "\n{:<6} {:<12} {:<8} {:<8} NODE",
+        "CORE", "TYPE", "SIBLING", "CLUSTER"
+    );
+    for core in &topology.cores {
+        println!(
+            "{:<6} {:<12} {:<8} {:<8} {}",
+            core.id,
+            format!("{:?}", core.core_type),
+            core.sibling_id,
+            core.cluster_id,
+            core.numa_node
+        );
+    }
+
+    // CPUs without an online file can't be offlined, so they count as online
+    let online: Vec<usize> = topology
+        .cpu_ids
+        .iter()
+        .copied()
+        .filter(|&cpu| crate::system::cpu::read_cpu_online_state(cpu).unwrap_or(true))
+        .collect();
+    let mut protected: Vec<usize> = topology.protected_cores.iter().copied().collect();
+    protected.sort_unstable();
+    println!(
+        "\nOnline CPUs:  {:?} ({} of {})",
+        online,
+        online.len(),
+        topology.cpu_ids.len()
+    );
+    println!("Protected:    {:?}", protected);
+
+    let power_state = crate::system::power::get_power_state(&settings.power_supply_path)
+        .map_or_else(
+            |e| format!("unknown ({})", e),
+            |state| format!("{:?}", state),
+        );
+    println!("Power state:  {}", power_state);
+    match crate::system::power::get_battery_percentage(&settings.power_supply_path) {
+        Some(percent) => println!("Battery:      {:.0}%", percent),
+        None => println!("Battery:      not found"),
+    }
+
+    let capabilities = crate::system::capabilities::probe();
+    let access = |writable: bool| {
+        if writable {
+            "writable"
+        } else {
+            "not writable (needs root, or not supported)"
+        }
+    };
+    println!("\nPermissions:");
+    println!("  Core hotplug: {}", access(capabilities.hotplug));
+    println!("  EPP:          {}", access(capabilities.epp));
+    println!("  Governor:     {}", access(capabilities.governor));
+
+    println!("\nEffective settings:\n{}", settings.to_toml()?);
+    Ok(())
+}
+
+/// Toggles every controllable core off and back on, one at a time, and reports the result.
+/// Protected cores and cores below `manage_cores_from` are left alone. With dry_run it only
+/// lists the cores it would toggle.
+#[cfg(target_os = "linux")]
+fn run_selftest(settings: &crate::config::Settings) -> Result<(), Box<dyn Error>> {
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))?;
+
+    let cores = crate::core::CoreManager::get_available_cores()?;
+    let mut topology = crate::core::topology::CPUTopology::new();
+    topology.set_protected_cores(
+        &crate::system::sysfs::LinuxSysfs,
+        Path::new(crate::core::topology::CPU_SYSFS_PATH),
+        &settings.protected_cores,
+    );
+    let mut failures = 0;
+    println!("{:<6} RESULT", "CPU");
+    for &cpu in &cores {
+        if !running.load(Ordering::SeqCst) {
+            println!("Interrupted, remaining cores not tested.");
+            break;
+        }
+        if topology.protected_cores.contains(&cpu) || cpu < settings.manage_cores_from {
+            println!("{:<6} skip (protected)", cpu);
+            continue;
+        }
+        match crate::system::cpu::read_cpu_online_state(cpu) {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("{:<6} skip (offline)", cpu);
+                continue;
+            }
+            Err(e) => {
+                println!("{:<6} FAIL (could not read state: {})", cpu, e);
+                failures += 1;
+                continue;
+            }
+        }
+        if settings.dry_run {
+            println!("{:<6} would toggle", cpu);
+            continue;
+        }
+        match crate::system::cpu::verify_toggle(cpu) {
+            Ok(()) => println!("{:<6} pass", cpu),
+            Err(e) => {
+                println!("{:<6} FAIL ({})", cpu, e);
+                failures += 1;
+            }
+        }
+    }
+
+    // Belt and braces: every tested core should already be back, but make sure
+    if !settings.dry_run {
+        for &cpu in cores
+            .iter()
+            .filter(|cpu| !topology.protected_cores.contains(cpu))
+        {
+            if crate::system::cpu::read_cpu_online_state(cpu).is_ok_and(|online| !online) {
+                let _ = crate::system::cpu::set_cpu_online_state(cpu, true);
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(format!("Self-test failed on {} core(s)", failures).into());
+    }
+    if settings.dry_run {
+        println!("Dry run: no core was toggled.");
+        return Ok(());
+    }
+    println!("Self-test passed: hotplug works and is reversible.");
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn run_selftest(_settings: &crate::config::Settings) -> Result<(), Box<dyn Error>> {
+    Err("The hotplug self-test is only supported on Linux".into())
+}
+
+/// Runs one load decision against the live system without writing anything, and fails if
+/// it would toggle a core or change EPP or the governor. Holds and interval gates are not
+/// simulated, as with --what-if.
+#[cfg(target_os = "linux")]
+fn assert_readonly(
+    args: &cli::Args,
+    settings: &crate::config::Settings,
+) -> Result<(), Box<dyn Error>> {
+    use crate::system::{cpu, cpufreq, power::PowerState};
+
+    let cores = crate::core::CoreManager::get_available_cores()?;
+    let online = cores
+        .iter()
+        .filter(|&&core| cpu::read_cpu_online_state(core).unwrap_or(true))
+        .count();
+
+    let load = match args.load {
+        Some(load) => load,
+        None => {
+            let mut sys = System::new();
+            sys.refresh_cpu_usage();
+            thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+            sys.refresh_cpu_usage();
+            let usage: f32 = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum();
+            usage / sys.cpus().len().max(1) as f32
+        }
+    };
+    let power_state = if args.on_battery {
+        PowerState::Battery
+    } else {
+        let state = crate::system::power::get_power_state(&settings.power_supply_path)
+            .unwrap_or(PowerState::Unknown);
+        settings.resolve_power_state(state)
+    };
+    let on_battery = power_state == PowerState::Battery;
+    let charge = args.charge.or_else(|| {
+        on_battery
+            .then(|| crate::system::power::get_battery_percentage(&settings.power_supply_path))
+            .flatten()
+    });
+
+    let mut writes = Vec::new();
+    let decision = crate::core::manager::simulate_decision(
+        settings,
+        cores.len(),
+        online,
+        load,
+        on_battery,
+        charge,
+    );
+    if decision.target_cores != online {
+        writes.push(format!(
+            "core count {} -> {} ({})",
+            online, decision.target_cores, decision.bound
+        ));
+    }
+
+    let profile = settings.active_profile(power_state, charge);
+    let driver = cpufreq::detect_driver();
+    let governor = match &profile.governor {
+        Some(governor) => Some(governor.as_str()),
+        None if driver != cpufreq::CpufreqDriver::None && !driver.supports_epp() => {
+            Some(cpufreq::governor_for_epp(&profile.epp))
+        }
+        None => None,
+    };
+    if let Some(governor) = governor {
+        for policy in cpufreq::policies_needing_governor(governor) {
+            writes.push(format!("{} governor -> '{}'", policy, governor));
+        }
+    }
+    if driver.supports_epp() {
+        for policy in crate::core::epp::list_epp_policies() {
+            if crate::core::epp::epp_would_change(policy, &profile.epp).unwrap_or(false) {
+                writes.push(format!("policy{} EPP -> '{}'", policy, profile.epp));
+            }
+        }
+    }
+
+    if writes.is_empty() {
+        println!(
+            "No writes at {:.1}% load on {:?}: {} cores stay online.",
+            load, power_state, online
+        );
+        return Ok(());
+    }
+    for write in &writes {
+        println!("Would write: {}", write);
+    }
+    Err(format!("{} sysfs write(s) would be made", writes.len()).into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn assert_readonly(
+    _args: &cli::Args,
+    _settings: &crate::config::Settings,
+) -> Result<(), Box<dyn Error>> {
+    Err("--assert-readonly is only supported on Linux".into())
+}
+
+/// `observer apply <profile>`: sets the profile's core count and power preference, then
+/// exits without restoring cores.
+fn apply_profile(
+    args: &cli::Args,
+    settings: crate::config::Settings,
+) -> Result<(), Box<dyn Error>> {
+    let name = args.profile().unwrap_or_default();
+    let profile = settings.profile_by_name(name).ok_or_else(|| {
+        let mut known: Vec<&str> = vec!["performance", "balanced", "powersave"];
+        known.extend(settings.profiles.keys().map(String::as_str));
+        known.sort_unstable();
+        known.dedup();
+        format!(
+            "Unknown profile '{}' (available: {})",
+            name,
+            known.join(", ")
+        )
+    })?;
+
+    let mut core_manager = crate::core::CoreManager::new(settings)?;
+    let cores = core_manager.apply_fixed_profile(profile)?;
+    println!("Applied profile '{}': {} cores online", name, cores);
+    Ok(())
+}
+
+// Exit status when exit_if_no_control finds nothing to control; the unit file stops
+// restarting on it
+const EXIT_NO_CONTROL: i32 = 3;
+
+// How often a sleeping loop checks for shutdown and the staleness bound
+const SLEEP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Tells systemd about a state change; a no-op unless started as a Type=notify service.
+fn sd_notify(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        logging::log_deduplicated(
+            log::Level::Warn,
+            &format!("Failed to notify systemd ({}): {}", state, e),
+        );
+    }
+}
+
+/// Sleeps until the next check is due, polling so shutdown and the decision staleness bound
+/// are honoured without waiting out a long interval.
+fn sleep_until_next_check(
+    settings: &crate::config::Settings,
+    deadline: Option<Instant>,
+    running: &AtomicBool,
+) {
+    let last_sample = Instant::now();
+    let mut wake_at = last_sample + Duration::from_secs(settings.check_interval_sec);
+    if let Some(staleness) = settings.max_decision_staleness_sec {
+        wake_at = wake_at.min(last_sample + Duration::from_secs(staleness));
+    }
+    if let Some(deadline) = deadline {
+        wake_at = wake_at.min(deadline);
+    }
+    debug!(
+        "Sleeping for {:.1} seconds",
+        wake_at.saturating_duration_since(last_sample).as_secs_f32()
+    );
+
+    while running.load(Ordering::SeqCst) {
+        let remaining = wake_at.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        thread::sleep(remaining.min(SLEEP_POLL_INTERVAL));
+    }
+}
+
+/// Re-reads the config files and swaps the result into the running manager. A config that
+/// fails to load keeps the current settings.
+fn reload_settings(
+    args: &cli::Args,
+    core_manager: &mut crate::core::CoreManager,
+    settings: &mut crate::config::Settings,
+) {
+    match crate::config::try_load_config() {
+        Ok(mut reloaded) => {
+            args.apply_overrides(&mut reloaded);
+            logging::set_backend(reloaded.log_backend);
+            core_manager.update_settings(reloaded.clone());
+            core_manager.refresh_core_inventory();
+            *settings = reloaded;
+        }
+        Err(e) => warn!(
+            "Failed to load the configuration, keeping the current settings: {}",
+            e
+        ),
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    logging::init();
+    let args = cli::Args::parse();
+    if let Some(path) = &args.config_path {
+        crate::config::set_config_file(path.clone());
+    }
+    // The service and apply log what they change; the other modes only print
+    let command = args.command();
+    if matches!(command, Command::Run | Command::Apply) {
+        logging::init(args.log_level);
+    }
+
+    // Every mode sees the same settings: command line over config files over defaults
+    let mut settings = crate::config::load_config().unwrap_or_default();
+    args.apply_overrides(&mut settings);
+    match command {
+        Command::Cores => return print_cores(),
+        Command::WhatIf => return print_what_if(&args, &settings),
+        Command::SelfTest => return run_selftest(&settings),
+        Command::Status => return print_status(&settings),
+        Command::AssertReadonly => return assert_readonly(&args, &settings),
+        Command::Apply => return apply_profile(&args, settings),
+        Command::Info => return print_info(&settings),
+        Command::Run => {}
+    }
+
     println!("Starting Observer...");
     info!("Starting Observer");
 
@@ -28,18 +503,58 @@ fn main() -> Result<(), Box<dyn Error>> {
         available_cores
     );
 
-    let settings = match crate::config::load_config() {
-        Ok(s) => {
-            info!("Loaded configuration: {:?}", s.clone());
-            s
+    logging::set_backend(settings.log_backend);
+    info!("Loaded configuration: {:?}", settings);
+    if settings.log_resolved_config {
+        match settings.to_toml() {
+            Ok(toml) => info!("Effective configuration:\n{}", toml),
+            Err(e) => warn!("Could not render configuration as TOML: {}", e),
         }
-        Err(e) => {
-            warn!("Failed to load config, using defaults: {}", e);
-            crate::config::Settings::default()
+    }
+    logging::set_dedup_interval(settings.log_dedup_sec);
+    if settings.dry_run {
+        info!("Dry run: logging core and EPP changes without writing them");
+    }
+
+    #[cfg(not(feature = "remote_target"))]
+    if let Some(url) = &settings.target_cores_url {
+        warn!(
+            "target_cores_url = {} is set, but observer was built without the remote_target feature",
+            url
+        );
+    }
+
+    if let Some(url) = &settings.webhook_url {
+        #[cfg(feature = "webhook")]
+        match utils::webhook::init(url) {
+            Ok(()) => info!("Posting change events to {}", url),
+            Err(e) => warn!("Webhook disabled: {}", e),
         }
-    };
+        #[cfg(not(feature = "webhook"))]
+        warn!(
+            "webhook_url = {} is set, but observer was built without the webhook feature",
+            url
+        );
+    }
 
-    info!("Loaded configuration: {:?}", settings);
+    #[cfg(feature = "metrics")]
+    let metrics = settings.metrics_addr.as_ref().and_then(|addr| {
+        let shared = utils::metrics::SharedMetrics::default();
+        match utils::metrics::serve(addr, shared.clone()) {
+            Ok(()) => Some(shared),
+            Err(e) => {
+                warn!("Failed to serve metrics on {}: {}", addr, e);
+                None
+            }
+        }
+    });
+    #[cfg(not(feature = "metrics"))]
+    if let Some(addr) = &settings.metrics_addr {
+        warn!(
+            "metrics_addr = {} is set, but observer was built without the metrics feature",
+            addr
+        );
+    }
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -49,49 +564,193 @@ fn main() -> Result<(), Box<dyn Error>> {
     })
     .expect("Error setting Ctrl-C handler");
 
+    if let Err(e) = signals::install_pause_toggle() {
+        warn!("Could not install SIGUSR2 pause handler: {}", e);
+    }
+    if let Err(e) = signals::install_reload() {
+        warn!("Could not install SIGHUP reload handler: {}", e);
+    }
+    if settings.track_power_durations {
+        if let Err(e) = signals::install_duration_reset() {
+            warn!("Could not install SIGUSR1 duration reset handler: {}", e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let capabilities = crate::system::capabilities::probe();
+        info!(
+            "Control available: hotplug {}, EPP {}, governor {}",
+            capabilities.hotplug, capabilities.epp, capabilities.governor
+        );
+        if !capabilities.any() && settings.exit_if_no_control {
+            error!(
+                "Neither core hotplug, EPP nor the governor is writable here; nothing to do. \
+                 Exiting (exit_if_no_control = true)."
+            );
+            std::process::exit(EXIT_NO_CONTROL);
+        }
+    }
+
     info!("Initializing Core Manager...");
     let mut core_manager = crate::core::CoreManager::new(settings.clone())?;
     info!("Core Manager initialized successfully.");
+    sd_notify("READY=1");
+    let watchdog = systemd::watchdog_enabled();
+    if watchdog {
+        info!("systemd watchdog enabled, pinging every iteration");
+    }
+    #[cfg(unix)]
+    let status_socket = settings.status_socket_path.as_ref().and_then(|path| {
+        let shared = SharedSnapshot::default();
+        match StatusSocket::bind(Path::new(path), shared.clone()) {
+            Ok(socket) => Some((socket, shared)),
+            Err(e) => {
+                warn!("Failed to open status socket {}: {}", path, e);
+                None
+            }
+        }
+    });
+    if args.once {
+        // Leave the decision in place and give the first load sample a real baseline
+        core_manager.one_shot();
+        core_manager.observe();
+        thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    }
 
     info!("Starting main loop...");
 
-    let check_interval = settings.check_interval_sec;
-    #[cfg(target_os = "linux")]
-    let power_supply_path = "/sys/class/power_supply/";
+    let deadline = args.duration_sec.map(|secs| {
+        info!("Running for {} seconds", secs);
+        Instant::now() + Duration::from_secs(secs)
+    });
 
+    let mut config_modified = crate::config::config_modified();
+    let mut no_supply_logged = false;
+    let mut was_paused = false;
     info!("Starting main service loop");
     while running.load(Ordering::SeqCst) {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            info!("Requested duration elapsed, exiting...");
+            break;
+        }
+
         debug!("Main loop iteration");
+        if watchdog {
+            sd_notify("WATCHDOG=1");
+        }
+
+        if signals::take_reload() {
+            info!("SIGHUP received, reloading configuration");
+            config_modified = crate::config::config_modified();
+            reload_settings(&args, &mut core_manager, &mut settings);
+        } else if settings.auto_reload {
+            let modified = crate::config::config_modified();
+            if modified != config_modified {
+                config_modified = modified;
+                info!("Config file changed, reloading");
+                reload_settings(&args, &mut core_manager, &mut settings);
+            }
+        }
+
+        if signals::take_duration_reset() {
+            core_manager.reset_power_durations();
+        }
 
-        #[cfg(target_os = "linux")]
-        let power_state_result = crate::system::power::get_power_state(power_supply_path);
-        #[cfg(not(target_os = "linux"))]
+        let paused = signals::is_paused();
+        if paused != was_paused {
+            if paused {
+                info!("Paused by SIGUSR2: monitoring load only, no core or power changes.");
+            } else {
+                info!("Resumed by SIGUSR2.");
+            }
+            was_paused = paused;
+        }
+        if paused {
+            core_manager.observe();
+            if args.once {
+                break;
+            }
+            sleep_until_next_check(&settings, deadline, &running);
+            continue;
+        }
+
+        #[cfg(any(target_os = "linux", windows))]
+        let power_state_result = crate::system::power::get_power_state(&settings.power_supply_path);
+        #[cfg(not(any(target_os = "linux", windows)))]
         let power_state_result = Ok(crate::system::power::PowerState::AC);
 
-        match power_state_result {
-            Ok(power_state) => {
-                let on_battery = power_state == crate::system::power::PowerState::Battery;
-                debug!(
-                    "Current power state: {:?}, On Battery: {}",
-                    power_state, on_battery
+        let power_state = match power_state_result {
+            Ok(power_state) => power_state,
+            Err(e) => {
+                let assumed = match settings.unknown_power_means {
+                    AssumedPowerState::Ac => "AC",
+                    AssumedPowerState::Battery => "battery",
+                };
+                error!(
+                    "Failed to get power state: {}. Assuming {} power.",
+                    e, assumed
                 );
+                crate::system::power::PowerState::Unknown
+            }
+        };
+        if power_state == crate::system::power::PowerState::NoSupply && !no_supply_logged {
+            info!("No power supplies reported, treating the system as AC powered.");
+            no_supply_logged = true;
+        }
+        let on_battery =
+            settings.resolve_power_state(power_state) == crate::system::power::PowerState::Battery;
+        debug!(
+            "Current power state: {:?}, On Battery: {}",
+            power_state, on_battery
+        );
 
-                let optimal_cores = core_manager.get_optimal_core_count(on_battery)?;
-                debug!("Optimal core count: {}", optimal_cores);
+        let optimal_cores = core_manager.get_optimal_core_count(on_battery)?;
+        debug!("Optimal core count: {}", optimal_cores);
 
-                if let Err(e) = core_manager.manage_cpu_cores(optimal_cores) {
-                    error!("Failed to manage CPU cores: {}", e);
-                }
-            }
-            Err(e) => {
-                error!("Failed to get power state: {}. Assuming AC power.", e);
+        if let Err(e) = core_manager.manage_cpu_cores(optimal_cores) {
+            error!("Failed to manage CPU cores: {}", e);
+        }
+
+        if let Err(e) = core_manager.write_deadman_info() {
+            logging::log_deduplicated(
+                log::Level::Warn,
+                &format!("Failed to write deadman info: {}", e),
+            );
+        }
+
+        if let Some(status_file) = &settings.status_file {
+            if let Err(e) = core_manager.snapshot().write_to(Path::new(status_file)) {
+                warn!("Failed to write status file {}: {}", status_file, e);
             }
         }
+        #[cfg(unix)]
+        if let Some((_, shared)) = &status_socket {
+            *shared.lock().unwrap_or_else(|e| e.into_inner()) = core_manager.snapshot();
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(shared) = &metrics {
+            let battery = crate::system::power::get_battery_percentage(&settings.power_supply_path);
+            shared
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .update(&core_manager.snapshot(), battery);
+        }
 
-        debug!("Sleeping for {} seconds", check_interval);
-        thread::sleep(Duration::from_secs(check_interval));
+        if args.once {
+            info!("Single iteration done (--once), exiting");
+            break;
+        }
+        sleep_until_next_check(&settings, deadline, &running);
     }
 
+    if !core_manager.hotplug_counts().is_empty() {
+        info!(
+            "Hotplug counts per core: {:?}",
+            core_manager.hotplug_counts()
+        );
+    }
     info!("Service shutting down");
+    sd_notify("STOPPING=1");
     Ok(())
 }
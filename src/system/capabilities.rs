@@ -0,0 +1,91 @@
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+use crate::core::topology::CPU_SYSFS_PATH;
+
+/// Which power levers this process can actually write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub hotplug: bool,  // Some cpuN/online other than the boot CPU
+    pub epp: bool,      // Some policy's energy_performance_preference
+    pub governor: bool, // Some policy's scaling_governor
+}
+
+impl Capabilities {
+    pub fn any(&self) -> bool {
+        self.hotplug || self.epp || self.governor
+    }
+}
+
+pub fn probe() -> Capabilities {
+    probe_in(Path::new(CPU_SYSFS_PATH))
+}
+
+/// Checks each lever by opening its file for writing. Nothing is written, so probing
+/// leaves the system untouched.
+pub fn probe_in(cpu_path: &Path) -> Capabilities {
+    let entries = |dir: &Path, prefix: &str| -> Vec<String> {
+        fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .filter(|name| {
+                        name.strip_prefix(prefix)
+                            .is_some_and(|n| n.parse::<usize>().is_ok())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let writable = |path: &Path| OpenOptions::new().write(true).open(path).is_ok();
+
+    let cpufreq = cpu_path.join("cpufreq");
+    let policies = entries(&cpufreq, "policy");
+    Capabilities {
+        hotplug: entries(cpu_path, "cpu")
+            .iter()
+            .filter(|name| *name != "cpu0")
+            .any(|name| writable(&cpu_path.join(name).join("online"))),
+        epp: policies
+            .iter()
+            .any(|policy| writable(&cpufreq.join(policy).join("energy_performance_preference"))),
+        governor: policies
+            .iter()
+            .any(|policy| writable(&cpufreq.join(policy).join("scaling_governor"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_finds_present_levers() {
+        let root =
+            std::env::temp_dir().join(format!("observer-capabilities-{}", std::process::id()));
+        fs::create_dir_all(root.join("cpu0")).unwrap();
+        fs::write(root.join("cpu0/online"), "1").unwrap();
+        fs::create_dir_all(root.join("cpufreq/policy0")).unwrap();
+        fs::write(root.join("cpufreq/policy0/scaling_governor"), "powersave").unwrap();
+
+        // The boot CPU alone doesn't make hotplug controllable
+        let found = probe_in(&root);
+        assert_eq!(
+            found,
+            Capabilities {
+                hotplug: false,
+                epp: false,
+                governor: true,
+            }
+        );
+        assert!(found.any());
+
+        fs::create_dir_all(root.join("cpu1")).unwrap();
+        fs::write(root.join("cpu1/online"), "1").unwrap();
+        assert!(probe_in(&root).hotplug);
+        assert!(!probe_in(&root.join("missing")).any());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,78 @@
+use log::debug;
+use std::fs;
+use std::path::Path;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Parses a cgroup v2 `cpu.max` value ("$MAX $PERIOD") into a number of cores.
+/// Returns `None` for "max" (unlimited) or malformed content.
+pub fn parse_cpu_max(content: &str) -> Option<f32> {
+    let mut parts = content.split_whitespace();
+    let quota = parts.next()?;
+    let period: f32 = parts.next().unwrap_or("100000").parse().ok()?;
+    if quota == "max" || period <= 0.0 {
+        return None;
+    }
+    let quota: f32 = quota.parse().ok()?;
+    Some(quota / period)
+}
+
+/// Effective CPU quota in cores for our cgroup, taking the tightest `cpu.max` between our
+/// cgroup and the root. `None` means unlimited or not running under cgroup v2.
+pub fn effective_cpu_quota() -> Option<f32> {
+    let proc_cgroup = fs::read_to_string("/proc/self/cgroup").ok()?;
+    quota_for(Path::new(CGROUP_ROOT), &proc_cgroup)
+}
+
+fn quota_for(cgroup_root: &Path, proc_cgroup: &str) -> Option<f32> {
+    // cgroup v2 has a single hierarchy line of the form "0::/some/path"
+    let cgroup_path = proc_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))?
+        .trim()
+        .trim_start_matches('/');
+
+    let mut dir = cgroup_root.join(cgroup_path);
+    let mut quota: Option<f32> = None;
+    loop {
+        if let Some(cores) = fs::read_to_string(dir.join("cpu.max"))
+            .ok()
+            .and_then(|c| parse_cpu_max(&c))
+        {
+            debug!("cgroup {} allows {:.2} cores", dir.display(), cores);
+            quota = Some(quota.map_or(cores, |q| q.min(cores)));
+        }
+        if dir == cgroup_root || !dir.pop() {
+            break;
+        }
+    }
+    quota
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_max() {
+        assert_eq!(parse_cpu_max("200000 100000\n"), Some(2.0));
+        assert_eq!(parse_cpu_max("50000 100000"), Some(0.5));
+        assert_eq!(parse_cpu_max("max 100000"), None);
+        assert_eq!(parse_cpu_max(""), None);
+    }
+
+    #[test]
+    fn test_nested_quota_takes_minimum() {
+        let root = std::env::temp_dir().join(format!("observer-cgroup-{}", std::process::id()));
+        let leaf = root.join("system.slice/observer.service");
+        fs::create_dir_all(&leaf).unwrap();
+        fs::write(root.join("system.slice/cpu.max"), "150000 100000").unwrap();
+        fs::write(leaf.join("cpu.max"), "max 100000").unwrap();
+
+        let quota = quota_for(&root, "0::/system.slice/observer.service\n");
+        assert_eq!(quota, Some(1.5));
+        assert_eq!(quota_for(&root, "1:name=systemd:/\n"), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
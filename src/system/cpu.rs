@@ -0,0 +1,50 @@
+use std::fs;
+use std::io;
+
+use crate::core::topology::CPU_SYSFS_PATH;
+
+/// Reads whether a logical CPU is online. CPUs without an `online` file (usually CPU0)
+/// cannot be offlined and are reported as online.
+pub fn read_cpu_online_state(cpu: usize) -> io::Result<bool> {
+    let path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, cpu);
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content.trim() == "1"),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let cpu_dir = format!("{}/cpu{}", CPU_SYSFS_PATH, cpu);
+            if fs::metadata(cpu_dir).is_ok() {
+                Ok(true)
+            } else {
+                Err(e)
+            }
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes a logical CPU's `online` file.
+pub fn set_cpu_online_state(cpu: usize, online: bool) -> io::Result<()> {
+    let path = format!("{}/cpu{}/online", CPU_SYSFS_PATH, cpu);
+    fs::write(path, if online { "1" } else { "0" })
+}
+
+/// Offlines `cpu`, checks it went offline, onlines it again and checks it came back. The
+/// CPU is always brought back online, even when the offline step fails.
+pub fn verify_toggle(cpu: usize) -> Result<(), String> {
+    let offline_result = set_cpu_online_state(cpu, false)
+        .map_err(|e| format!("offline failed: {}", e))
+        .and_then(|_| match read_cpu_online_state(cpu) {
+            Ok(false) => Ok(()),
+            Ok(true) => Err("still online after offlining".to_string()),
+            Err(e) => Err(format!("could not read state: {}", e)),
+        });
+
+    let online_result = set_cpu_online_state(cpu, true)
+        .map_err(|e| format!("re-online failed: {}", e))
+        .and_then(|_| match read_cpu_online_state(cpu) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("did not come back online".to_string()),
+            Err(e) => Err(format!("could not read state: {}", e)),
+        });
+
+    offline_result.and(online_result)
+}
@@ -0,0 +1,271 @@
+use log::{debug, info, warn};
+use std::fs;
+use std::path::Path;
+
+use crate::core::topology::CPU_SYSFS_PATH;
+
+/// The cpufreq scaling driver in use, which decides which power levers actually work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpufreqDriver {
+    IntelPstate,  // intel_pstate in active mode, EPP-driven
+    IntelCpufreq, // intel_pstate in passive mode, governor-driven
+    AmdPstateEpp, // amd-pstate in active mode, EPP-driven
+    AmdPstate,    // amd-pstate in passive/guided mode, governor-driven
+    AcpiCpufreq,
+    Other(String),
+    None, // No cpufreq support at all
+}
+
+impl CpufreqDriver {
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "intel_pstate" => Self::IntelPstate,
+            "intel_cpufreq" => Self::IntelCpufreq,
+            "amd-pstate-epp" => Self::AmdPstateEpp,
+            "amd-pstate" => Self::AmdPstate,
+            "acpi-cpufreq" => Self::AcpiCpufreq,
+            "" => Self::None,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Whether energy_performance_preference is honoured by this driver.
+    pub fn supports_epp(&self) -> bool {
+        matches!(self, Self::IntelPstate | Self::AmdPstateEpp)
+    }
+
+    fn turbo_file(&self) -> (&'static str, bool) {
+        match self {
+            // intel_pstate exposes the inverse: no_turbo = 1 disables boost
+            Self::IntelPstate | Self::IntelCpufreq => ("intel_pstate/no_turbo", true),
+            _ => ("cpufreq/boost", false),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn detect_driver() -> CpufreqDriver {
+    detect_driver_in(Path::new(CPU_SYSFS_PATH))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn detect_driver() -> CpufreqDriver {
+    warn!("cpufreq driver detection is only supported on Linux.");
+    CpufreqDriver::None
+}
+
+pub fn detect_driver_in(cpu_path: &Path) -> CpufreqDriver {
+    let Ok(entries) = fs::read_dir(cpu_path.join("cpufreq")) else {
+        return CpufreqDriver::None;
+    };
+    let mut policies: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with("policy"))
+        })
+        .collect();
+    policies.sort();
+
+    policies
+        .iter()
+        .find_map(|p| fs::read_to_string(p.join("scaling_driver")).ok())
+        .map(|name| CpufreqDriver::from_name(&name))
+        .unwrap_or(CpufreqDriver::None)
+}
+
+/// Closest governor to an EPP hint, for drivers that only understand governors.
+pub fn governor_for_epp(epp: &str) -> &'static str {
+    match epp {
+        "performance" => "performance",
+        "power" => "powersave",
+        _ => "schedutil",
+    }
+}
+
+/// Writes `scaling_governor` for every cpufreq policy that offers the requested governor.
+pub fn set_governor(governor: &str) -> Result<(), String> {
+    let base_path = Path::new(CPU_SYSFS_PATH).join("cpufreq");
+    let mut policies_updated = 0;
+
+    for entry in fs::read_dir(&base_path)
+        .map_err(|e| format!("Failed to read {}: {}", base_path.display(), e))?
+        .flatten()
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with("policy") {
+            continue;
+        }
+
+        if let Ok(available) = fs::read_to_string(path.join("scaling_available_governors")) {
+            if !available.split_whitespace().any(|g| g == governor) {
+                warn!(
+                    "Governor '{}' not available for {} (available: {})",
+                    governor,
+                    name,
+                    available.trim()
+                );
+                continue;
+            }
+        }
+
+        match fs::write(path.join("scaling_governor"), governor) {
+            Ok(_) => {
+                debug!("Set governor for {} to '{}'", name, governor);
+                policies_updated += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(format!("Permission denied setting governor for {}", name));
+            }
+            Err(e) => warn!("Failed to set governor for {}: {}", name, e),
+        }
+    }
+
+    if policies_updated == 0 {
+        warn!("Could not set governor '{}' for any CPU policy.", governor);
+    }
+    Ok(())
+}
+
+/// Names of the policies whose governor `set_governor(governor)` would change.
+pub fn policies_needing_governor(governor: &str) -> Vec<String> {
+    let base_path = Path::new(CPU_SYSFS_PATH).join("cpufreq");
+    let Ok(entries) = fs::read_dir(&base_path) else {
+        return Vec::new();
+    };
+    let mut policies: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("policy"))
+        .filter(|entry| {
+            let path = entry.path();
+            let offered = fs::read_to_string(path.join("scaling_available_governors"))
+                .map_or(true, |available| {
+                    available.split_whitespace().any(|g| g == governor)
+                });
+            let current = fs::read_to_string(path.join("scaling_governor")).unwrap_or_default();
+            offered && current.trim() != governor
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    policies.sort();
+    policies
+}
+
+/// Enables or disables turbo/boost through the driver-appropriate file.
+pub fn set_turbo(driver: &CpufreqDriver, enabled: bool) -> Result<(), String> {
+    let (file, inverted) = driver.turbo_file();
+    let path = Path::new(CPU_SYSFS_PATH).join(file);
+    if !path.exists() {
+        debug!("Turbo control {} not present, skipping.", path.display());
+        return Ok(());
+    }
+
+    let value = if enabled != inverted { "1" } else { "0" };
+    fs::write(&path, value).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!(
+        "Turbo {} via {}",
+        if enabled { "enabled" } else { "disabled" },
+        path.display()
+    );
+    Ok(())
+}
+
+/// Picks the highest frequency in `available` at or below `target_khz`, or the lowest one
+/// when all of them are above it. Returns `target_khz` when the list is empty.
+pub fn snap_to_available(target_khz: u64, available: &[u64]) -> u64 {
+    available
+        .iter()
+        .copied()
+        .filter(|&freq| freq <= target_khz)
+        .max()
+        .or_else(|| available.iter().copied().min())
+        .unwrap_or(target_khz)
+}
+
+/// Caps every policy's `scaling_max_freq` at `percent` of its hardware maximum. Drivers with
+/// discrete steps get the nearest listed frequency at or below the target; continuous
+/// drivers get the computed value.
+pub fn set_max_freq_percent(percent: u32) -> Result<(), String> {
+    let base_path = Path::new(CPU_SYSFS_PATH).join("cpufreq");
+    let read_khz =
+        |path: &Path| -> Option<u64> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+
+    for entry in fs::read_dir(&base_path)
+        .map_err(|e| format!("Failed to read {}: {}", base_path.display(), e))?
+        .flatten()
+    {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with("policy") {
+            continue;
+        }
+        let Some(max_khz) = read_khz(&path.join("cpuinfo_max_freq")) else {
+            debug!("No cpuinfo_max_freq for {}, skipping", name);
+            continue;
+        };
+        let min_khz = read_khz(&path.join("cpuinfo_min_freq")).unwrap_or(0);
+
+        let mut target = (max_khz * u64::from(percent.min(100)) / 100).max(min_khz);
+        if let Ok(list) = fs::read_to_string(path.join("scaling_available_frequencies")) {
+            let available: Vec<u64> = list
+                .split_whitespace()
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            target = snap_to_available(target, &available);
+        }
+
+        match fs::write(path.join("scaling_max_freq"), target.to_string()) {
+            Ok(_) => debug!("Set scaling_max_freq for {} to {} kHz", name, target),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(format!(
+                    "Permission denied setting max frequency for {}",
+                    name
+                ));
+            }
+            Err(e) => warn!("Failed to set max frequency for {}: {}", name, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_driver() {
+        let root = std::env::temp_dir().join(format!("observer-cpufreq-{}", std::process::id()));
+        let policy = root.join("cpufreq/policy0");
+        fs::create_dir_all(&policy).unwrap();
+
+        assert_eq!(detect_driver_in(&root), CpufreqDriver::None);
+
+        fs::write(policy.join("scaling_driver"), "amd-pstate-epp\n").unwrap();
+        let driver = detect_driver_in(&root);
+        assert_eq!(driver, CpufreqDriver::AmdPstateEpp);
+        assert!(driver.supports_epp());
+
+        fs::write(policy.join("scaling_driver"), "acpi-cpufreq\n").unwrap();
+        assert!(!detect_driver_in(&root).supports_epp());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_governor_for_epp() {
+        assert_eq!(governor_for_epp("performance"), "performance");
+        assert_eq!(governor_for_epp("power"), "powersave");
+        assert_eq!(governor_for_epp("balance_power"), "schedutil");
+    }
+
+    #[test]
+    fn test_snap_to_available() {
+        let steps = [800000, 1600000, 2400000, 3200000];
+        assert_eq!(snap_to_available(2000000, &steps), 1600000);
+        assert_eq!(snap_to_available(2400000, &steps), 2400000);
+        assert_eq!(snap_to_available(500000, &steps), 800000);
+        assert_eq!(snap_to_available(2000000, &[]), 2000000);
+    }
+}
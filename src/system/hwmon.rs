@@ -0,0 +1,53 @@
+use log::debug;
+use std::fs;
+use std::path::Path;
+
+pub const HWMON_PATH: &str = "/sys/class/hwmon";
+
+/// Highest fan speed in RPM across all hwmon devices, or None when no fan reports one.
+pub fn read_max_fan_rpm() -> Option<u32> {
+    read_max_fan_rpm_in(Path::new(HWMON_PATH))
+}
+
+pub fn read_max_fan_rpm_in(hwmon_path: &Path) -> Option<u32> {
+    let devices = match fs::read_dir(hwmon_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Could not read {}: {}", hwmon_path.display(), e);
+            return None;
+        }
+    };
+
+    devices
+        .flatten()
+        .filter_map(|device| fs::read_dir(device.path()).ok())
+        .flat_map(|files| files.flatten())
+        .filter(|file| {
+            let name = file.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("fan") && name.ends_with("_input")
+        })
+        .filter_map(|file| fs::read_to_string(file.path()).ok()?.trim().parse().ok())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_max_fan_rpm() {
+        let root = std::env::temp_dir().join(format!("observer-hwmon-{}", std::process::id()));
+        fs::create_dir_all(root.join("hwmon0")).unwrap();
+        fs::create_dir_all(root.join("hwmon1")).unwrap();
+        fs::write(root.join("hwmon0/fan1_input"), "1200\n").unwrap();
+        fs::write(root.join("hwmon1/fan1_input"), "2900\n").unwrap();
+        fs::write(root.join("hwmon1/fan2_input"), "800\n").unwrap();
+        fs::write(root.join("hwmon1/temp1_input"), "65000\n").unwrap();
+
+        assert_eq!(read_max_fan_rpm_in(&root), Some(2900));
+        assert_eq!(read_max_fan_rpm_in(&root.join("missing")), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
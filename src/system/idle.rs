@@ -0,0 +1,110 @@
+use log::debug;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::InputIdleSource;
+
+/// Something that can report how long the user has been away from the keyboard.
+pub trait IdleSource {
+    fn name(&self) -> &'static str;
+    /// Time since the last user input, or `None` if it can't be determined.
+    fn idle_duration(&mut self) -> Option<Duration>;
+}
+
+/// Reads logind's per-session IdleHint through `loginctl`, taking the least idle session.
+pub struct LogindIdle;
+
+impl LogindIdle {
+    fn loginctl(args: &[&str]) -> Option<String> {
+        let output = Command::new("loginctl").args(args).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    }
+
+    fn session_idle(session: &str) -> Option<Duration> {
+        let props = Self::loginctl(&[
+            "show-session",
+            session,
+            "--property=IdleHint",
+            "--property=IdleSinceHint",
+        ])?;
+
+        let mut idle_hint = None;
+        let mut idle_since_usec = None;
+        for line in props.lines() {
+            match line.split_once('=') {
+                Some(("IdleHint", value)) => idle_hint = Some(value == "yes"),
+                Some(("IdleSinceHint", value)) => idle_since_usec = value.parse::<u64>().ok(),
+                _ => {}
+            }
+        }
+
+        if !idle_hint? {
+            return Some(Duration::ZERO);
+        }
+        let since = UNIX_EPOCH + Duration::from_micros(idle_since_usec?);
+        Some(SystemTime::now().duration_since(since).unwrap_or_default())
+    }
+}
+
+impl IdleSource for LogindIdle {
+    fn name(&self) -> &'static str {
+        "logind"
+    }
+
+    fn idle_duration(&mut self) -> Option<Duration> {
+        let sessions = Self::loginctl(&["list-sessions", "--no-legend"])?;
+        sessions
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter_map(Self::session_idle)
+            .min()
+    }
+}
+
+/// Uses the last access time of terminal devices, the same signal `w` reports as IDLE.
+pub struct TtyIdle;
+
+impl IdleSource for TtyIdle {
+    fn name(&self) -> &'static str {
+        "tty"
+    }
+
+    fn idle_duration(&mut self) -> Option<Duration> {
+        let mut most_recent: Option<SystemTime> = None;
+        for dir in ["/dev/pts", "/dev"] {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                let is_tty = dir == "/dev/pts" && name.parse::<u32>().is_ok()
+                    || name
+                        .strip_prefix("tty")
+                        .is_some_and(|n| !n.is_empty() && n.parse::<u32>().is_ok());
+                if !is_tty {
+                    continue;
+                }
+                if let Ok(accessed) = entry.metadata().and_then(|m| m.accessed()) {
+                    most_recent = Some(most_recent.map_or(accessed, |t| t.max(accessed)));
+                }
+            }
+        }
+        let idle = SystemTime::now()
+            .duration_since(most_recent?)
+            .unwrap_or_default();
+        debug!("tty idle time: {:?}", idle);
+        Some(idle)
+    }
+}
+
+pub fn source_for(kind: InputIdleSource) -> Box<dyn IdleSource> {
+    match kind {
+        InputIdleSource::Logind => Box::new(LogindIdle),
+        InputIdleSource::Tty => Box::new(TtyIdle),
+    }
+}
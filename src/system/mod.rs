@@ -1,4 +1,14 @@
+pub mod capabilities;
+pub mod cgroup;
+pub mod cpu;
+pub mod cpufreq;
+pub mod hwmon;
+pub mod idle;
 pub mod power;
+pub mod powerclamp;
+pub mod rapl;
+pub mod sysfs;
+pub mod thermal;
 
 // Remove unused direct exports
 // pub use cpu::{read_cpu_online_state, set_cpu_online_state};
@@ -2,60 +2,350 @@ use log::{debug, warn};
 use std::error::Error;
 
 #[cfg(target_os = "linux")]
-use std::fs;
+use super::sysfs::{LinuxSysfs, SysfsProvider};
+#[cfg(target_os = "linux")]
+use std::{path::Path, thread, time::Duration};
+
+#[cfg(target_os = "linux")]
+const READ_DIR_ATTEMPTS: u32 = 3;
+#[cfg(target_os = "linux")]
+const READ_DIR_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Lists the power_supply directory, retrying briefly since sysfs can be transiently busy.
+#[cfg(target_os = "linux")]
+fn read_power_supplies(
+    sysfs: &dyn SysfsProvider,
+    power_path: &Path,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut attempt = 1;
+    loop {
+        match sysfs.list(power_path) {
+            Ok(entries) => return Ok(entries),
+            Err(e) if attempt < READ_DIR_ATTEMPTS => {
+                debug!(
+                    "Reading {} failed (attempt {}/{}): {}. Retrying.",
+                    power_path.display(),
+                    attempt,
+                    READ_DIR_ATTEMPTS,
+                    e
+                );
+                thread::sleep(READ_DIR_RETRY_DELAY);
+                attempt += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Whether the supply `name` has the given `type`, falling back to `name_matches` for
+/// supplies without a type file.
+#[cfg(target_os = "linux")]
+fn supply_is(
+    sysfs: &dyn SysfsProvider,
+    power_path: &Path,
+    name: &str,
+    kind: &str,
+    name_matches: fn(&str) -> bool,
+) -> bool {
+    match sysfs.read(&power_path.join(name).join("type")) {
+        Ok(content) => content.trim() == kind,
+        Err(_) => name_matches(name),
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PowerState {
     AC,
     Battery,
     Unknown,
+    NoSupply, // Empty power_supply directory, typical for VMs and desktops without ACPI PSUs
 }
 
 #[cfg(target_os = "linux")]
 pub fn get_power_state(power_path: &str) -> Result<PowerState, Box<dyn Error>> {
-    let entries = fs::read_dir(power_path)?;
-
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.to_string_lossy().contains("AC") {
-            let online_path = path.join("online");
-            match fs::read_to_string(&online_path) {
-                Ok(content) => {
-                    let state = if content.trim() == "1" {
-                        PowerState::AC
-                    } else {
-                        PowerState::Battery
-                    };
-                    debug!(
-                        "Detected power state from {}: {:?}",
-                        online_path.display(),
-                        state
-                    );
-                    return Ok(state); // Return the first definite state found
-                }
-                Err(_) => {
-                    warn!(
-                        "Could not read {}: {}",
-                        online_path.display(),
-                        "status cannot be read"
-                    );
-                    return Ok(PowerState::Unknown);
-                }
+    power_state_in(&LinuxSysfs, Path::new(power_path))
+}
+
+/// Checks every `Mains` supply (AC, ACAD, ADP1, ...): AC when any of them is online,
+/// Battery when they all read offline, Unknown when there is no mains supply or none
+/// of them can be read.
+#[cfg(target_os = "linux")]
+pub fn power_state_in(
+    sysfs: &dyn SysfsProvider,
+    power_path: &Path,
+) -> Result<PowerState, Box<dyn Error>> {
+    let entries = read_power_supplies(sysfs, power_path)?;
+    if entries.is_empty() {
+        debug!(
+            "{} is empty, no power supplies present.",
+            power_path.display()
+        );
+        return Ok(PowerState::NoSupply);
+    }
+
+    let mut found_mains = false;
+    let mut state = PowerState::Unknown;
+    for name in entries {
+        if !supply_is(sysfs, power_path, &name, "Mains", |name| {
+            name.contains("AC") || name.starts_with("ADP")
+        }) {
+            continue;
+        }
+        found_mains = true;
+
+        let online_path = power_path.join(&name).join("online");
+        match sysfs.read(&online_path) {
+            Ok(content) if content.trim() == "1" => {
+                debug!("{} reports mains power online", online_path.display());
+                return Ok(PowerState::AC);
             }
+            Ok(_) => state = PowerState::Battery,
+            Err(e) => warn!("Could not read {}: {}", online_path.display(), e),
         }
     }
 
-    warn!(
-        "No AC power supply found or readable in {}. Assuming unknown.",
-        power_path
-    );
-    Ok(PowerState::Unknown) // No AC adapter found or readable
+    if !found_mains {
+        warn!(
+            "No mains power supply found in {}. Assuming unknown.",
+            power_path.display()
+        );
+    }
+    debug!("Detected power state: {:?}", state);
+    Ok(state)
+}
+
+/// Average charge across all batteries in `power_path`, in percent, or None when no
+/// battery reports one. Batteries are supplies whose `type` is "Battery", or named BAT*
+/// when the type file is missing.
+#[cfg(target_os = "linux")]
+pub fn get_battery_percentage(power_path: &str) -> Option<f32> {
+    battery_percentage_in(&LinuxSysfs, Path::new(power_path))
+}
+
+#[cfg(target_os = "linux")]
+pub fn battery_percentage_in(sysfs: &dyn SysfsProvider, power_path: &Path) -> Option<f32> {
+    let read = |name: &str, file: &str| -> Option<f32> {
+        sysfs
+            .read(&power_path.join(name).join(file))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    };
+    let charges: Vec<f32> = read_power_supplies(sysfs, power_path)
+        .ok()?
+        .into_iter()
+        .filter(|name| {
+            supply_is(sysfs, power_path, name, "Battery", |name| {
+                name.starts_with("BAT")
+            })
+        })
+        .filter_map(|name| {
+            // Some batteries only report energy counters, not a ready-made capacity
+            read(&name, "capacity").or_else(|| {
+                let full = read(&name, "energy_full").filter(|full| *full > 0.0)?;
+                Some((read(&name, "energy_now")? / full * 100.0).min(100.0))
+            })
+        })
+        .collect();
+    if charges.is_empty() {
+        return None;
+    }
+    Some(charges.iter().sum::<f32>() / charges.len() as f32)
+}
+
+/// ACLineStatus, BatteryFlag and BatteryLifePercent values from GetSystemPowerStatus.
+#[cfg(windows)]
+mod windows_status {
+    pub const AC_OFFLINE: u8 = 0;
+    pub const AC_ONLINE: u8 = 1;
+    pub const NO_SYSTEM_BATTERY: u8 = 128;
+    pub const UNKNOWN: u8 = 255;
+}
+
+#[cfg(windows)]
+fn system_power_status(
+) -> Result<windows::Win32::System::Power::SYSTEM_POWER_STATUS, Box<dyn Error>> {
+    use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status = SYSTEM_POWER_STATUS::default();
+    // SAFETY: status is a valid, writable SYSTEM_POWER_STATUS for the duration of the call
+    unsafe { GetSystemPowerStatus(&mut status)? };
+    Ok(status)
+}
+
+/// Maps ACLineStatus to a power state. Machines without a system battery (desktops, most
+/// VMs) report NoSupply when Windows can't tell the AC line status either.
+#[cfg(windows)]
+fn power_state_from(ac_line_status: u8, battery_flag: u8) -> PowerState {
+    use windows_status::*;
+    match ac_line_status {
+        AC_ONLINE => PowerState::AC,
+        AC_OFFLINE => PowerState::Battery,
+        _ if battery_flag == NO_SYSTEM_BATTERY => PowerState::NoSupply,
+        _ => PowerState::Unknown,
+    }
 }
 
-#[cfg(not(target_os = "linux"))]
+/// Battery charge from BatteryLifePercent, or None without a system battery.
+#[cfg(windows)]
+fn battery_percentage_from(battery_flag: u8, battery_life_percent: u8) -> Option<f32> {
+    use windows_status::*;
+    if battery_flag == NO_SYSTEM_BATTERY || battery_life_percent == UNKNOWN {
+        return None;
+    }
+    Some(f32::from(battery_life_percent.min(100)))
+}
+
+/// `power_path` is a sysfs location and unused on Windows.
+#[cfg(windows)]
+pub fn get_power_state(_power_path: &str) -> Result<PowerState, Box<dyn Error>> {
+    let status = system_power_status()?;
+    let state = power_state_from(status.ACLineStatus, status.BatteryFlag);
+    if state == PowerState::Unknown {
+        warn!("GetSystemPowerStatus reports an unknown AC line status. Assuming unknown.");
+    }
+    debug!("Detected power state: {:?}", state);
+    Ok(state)
+}
+
+#[cfg(windows)]
+pub fn get_battery_percentage(_power_path: &str) -> Option<f32> {
+    let status = system_power_status().ok()?;
+    battery_percentage_from(status.BatteryFlag, status.BatteryLifePercent)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn get_battery_percentage(_power_path: &str) -> Option<f32> {
+    None
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
 pub fn get_power_state(_power_path: &str) -> Result<PowerState, Box<dyn Error>> {
-    warn!("Power status detection is only supported on Linux. Assuming Unknown power state.");
+    warn!("Power status detection is only supported on Linux and Windows. Assuming Unknown power state.");
     Ok(PowerState::Unknown)
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::system::sysfs::MockSysfs;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn fake_power_supply(name: &str, ac_online: Option<&str>) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("observer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("BAT0")).unwrap();
+        fs::write(root.join("BAT0/capacity"), "80\n").unwrap();
+        if let Some(online) = ac_online {
+            // Mirror sysfs, where supplies are symlinks to device directories
+            let device = root.join("devices/ACPI0003:00/power_supply/AC");
+            fs::create_dir_all(&device).unwrap();
+            fs::write(device.join("online"), online).unwrap();
+            std::os::unix::fs::symlink(&device, root.join("AC")).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_power_state_from_fake_sysfs() {
+        let on_ac = fake_power_supply("power-ac", Some("1\n"));
+        let on_battery = fake_power_supply("power-battery", Some("0\n"));
+        let no_adapter = fake_power_supply("power-none", None);
+        let empty =
+            std::env::temp_dir().join(format!("observer-power-empty-{}", std::process::id()));
+        fs::create_dir_all(&empty).unwrap();
+
+        assert_eq!(
+            get_power_state(on_ac.to_str().unwrap()).unwrap(),
+            PowerState::AC
+        );
+        assert_eq!(
+            get_power_state(on_battery.to_str().unwrap()).unwrap(),
+            PowerState::Battery
+        );
+        assert_eq!(
+            get_power_state(no_adapter.to_str().unwrap()).unwrap(),
+            PowerState::Unknown
+        );
+
+        assert_eq!(
+            get_power_state(empty.to_str().unwrap()).unwrap(),
+            PowerState::NoSupply
+        );
+        assert!(get_power_state("/nonexistent/observer/power_supply").is_err());
+
+        assert_eq!(
+            get_battery_percentage(on_battery.to_str().unwrap()),
+            Some(80.0)
+        );
+        assert_eq!(get_battery_percentage(empty.to_str().unwrap()), None);
+
+        for root in [on_ac, on_battery, no_adapter, empty] {
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_power_state_with_two_mains_adapters() {
+        let sysfs = MockSysfs::new();
+        let root = Path::new("/power_supply");
+        for (name, kind) in [
+            ("ACAD", "Mains"),
+            ("ADP1", "Mains"),
+            ("BAT0", "Battery"),
+            ("BAT1", "Battery"),
+        ] {
+            sysfs.insert(root.join(name).join("type"), kind);
+        }
+        sysfs.insert(root.join("ACAD/online"), "0\n");
+        sysfs.insert(root.join("ADP1/online"), "1\n");
+        assert_eq!(power_state_in(&sysfs, root).unwrap(), PowerState::AC);
+
+        sysfs.write(&root.join("ADP1/online"), "0\n").unwrap();
+        assert_eq!(power_state_in(&sysfs, root).unwrap(), PowerState::Battery);
+
+        // Neither adapter readable: nothing says we're on battery
+        let unreadable = MockSysfs::new();
+        unreadable.insert(root.join("ACAD/type"), "Mains");
+        unreadable.insert(root.join("BAT0/type"), "Battery");
+        assert_eq!(
+            power_state_in(&unreadable, root).unwrap(),
+            PowerState::Unknown
+        );
+    }
+
+    #[test]
+    fn test_battery_percentage_averages_batteries() {
+        let sysfs = MockSysfs::new();
+        let root = Path::new("/power_supply");
+        sysfs.insert(root.join("AC/type"), "Mains");
+        sysfs.insert(root.join("AC/online"), "0");
+        assert_eq!(battery_percentage_in(&sysfs, root), None);
+
+        sysfs.insert(root.join("BAT0/type"), "Battery");
+        sysfs.insert(root.join("BAT0/capacity"), "90\n");
+        sysfs.insert(root.join("BAT1/type"), "Battery");
+        sysfs.insert(root.join("BAT1/energy_now"), "15000000\n");
+        sysfs.insert(root.join("BAT1/energy_full"), "50000000\n");
+        assert_eq!(battery_percentage_in(&sysfs, root), Some(60.0));
+    }
+}
+
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+
+    #[test]
+    fn test_system_power_status_mapping() {
+        assert_eq!(power_state_from(1, 8), PowerState::AC);
+        assert_eq!(power_state_from(0, 0), PowerState::Battery);
+        assert_eq!(power_state_from(255, 128), PowerState::NoSupply);
+        assert_eq!(power_state_from(255, 255), PowerState::Unknown);
+
+        assert_eq!(battery_percentage_from(1, 81), Some(81.0));
+        assert_eq!(battery_percentage_from(128, 255), None);
+        assert_eq!(battery_percentage_from(8, 255), None);
+    }
+}
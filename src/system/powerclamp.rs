@@ -0,0 +1,97 @@
+use log::warn;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "linux")]
+use log::debug;
+#[cfg(target_os = "linux")]
+use std::fs;
+
+/// Locates the `intel_powerclamp` cooling device under the given thermal class path
+/// (normally `/sys/class/thermal`), if the driver is loaded.
+#[cfg(target_os = "linux")]
+pub fn find_device(thermal_path: &str) -> Option<PathBuf> {
+    let entries = match fs::read_dir(thermal_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Could not read {}: {}", thermal_path, e);
+            return None;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with("cooling_device"))
+        {
+            continue;
+        }
+        if let Ok(device_type) = fs::read_to_string(path.join("type")) {
+            if device_type.trim() == "intel_powerclamp" {
+                debug!("Found intel_powerclamp at {}", path.display());
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn find_device(_thermal_path: &str) -> Option<PathBuf> {
+    warn!("Idle injection is only supported on Linux.");
+    None
+}
+
+/// Idle percentage to inject so that `total_cores` behave like `target_cores`.
+pub fn idle_percentage(target_cores: usize, total_cores: usize) -> u32 {
+    if total_cores == 0 || target_cores >= total_cores {
+        return 0;
+    }
+    let busy_fraction = target_cores as f32 / total_cores as f32;
+    ((1.0 - busy_fraction) * 100.0).round() as u32
+}
+
+/// Writes the idle percentage to the cooling device, capped at its `max_state`.
+#[cfg(target_os = "linux")]
+pub fn set_idle_percentage(device: &Path, percentage: u32) -> Result<u32, Box<dyn Error>> {
+    let max_state = fs::read_to_string(device.join("max_state"))
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(50);
+
+    let state = percentage.min(max_state);
+    if state < percentage {
+        warn!(
+            "Requested {}% idle exceeds powerclamp max_state {}, capping.",
+            percentage, max_state
+        );
+    }
+
+    fs::write(device.join("cur_state"), state.to_string())?;
+    debug!("Set powerclamp idle injection to {}%", state);
+    Ok(state)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_idle_percentage(_device: &Path, percentage: u32) -> Result<u32, Box<dyn Error>> {
+    warn!(
+        "Idle injection is only supported on Linux. Requested {}% ignored.",
+        percentage
+    );
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_percentage() {
+        assert_eq!(idle_percentage(8, 8), 0);
+        assert_eq!(idle_percentage(4, 8), 50);
+        assert_eq!(idle_percentage(6, 8), 25);
+        assert_eq!(idle_percentage(2, 0), 0);
+    }
+}
@@ -0,0 +1,84 @@
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub const RAPL_PACKAGE_PATH: &str = "/sys/class/powercap/intel-rapl:0";
+
+/// Package power from RAPL energy counters. Watts are the energy delta between two
+/// consecutive reads divided by the time between them, so the first read yields nothing.
+pub struct PackagePowerMeter {
+    zone: PathBuf,
+    max_energy_uj: Option<u64>, // Counter wraps back to zero past this value
+    last: Option<(u64, Instant)>,
+}
+
+impl PackagePowerMeter {
+    pub fn new(zone: &Path) -> Self {
+        let max_energy_uj = read_u64(&zone.join("max_energy_range_uj"));
+        Self {
+            zone: zone.to_path_buf(),
+            max_energy_uj,
+            last: None,
+        }
+    }
+
+    /// Average package power in watts since the previous call, or None on the first call
+    /// or when the counter can't be read (no RAPL, or not running as root).
+    pub fn read_package_power(&mut self) -> Option<f32> {
+        self.read_package_power_at(Instant::now())
+    }
+
+    fn read_package_power_at(&mut self, now: Instant) -> Option<f32> {
+        let Some(energy) = read_u64(&self.zone.join("energy_uj")) else {
+            debug!("Could not read RAPL energy from {}", self.zone.display());
+            self.last = None;
+            return None;
+        };
+        let previous = self.last.replace((energy, now));
+        let (last_energy, last_time) = previous?;
+
+        let elapsed = now.duration_since(last_time).as_secs_f32();
+        if elapsed <= 0.0 {
+            return None;
+        }
+        let delta = if energy >= last_energy {
+            energy - last_energy
+        } else {
+            // Counter wrapped; without the range the sample is unusable
+            self.max_energy_uj? - last_energy + energy
+        };
+        Some(delta as f32 / 1_000_000.0 / elapsed)
+    }
+}
+
+fn read_u64(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_package_power_from_energy_deltas() {
+        let zone = std::env::temp_dir().join(format!("observer-rapl-{}", std::process::id()));
+        fs::create_dir_all(&zone).unwrap();
+        fs::write(zone.join("max_energy_range_uj"), "1000000000").unwrap();
+        fs::write(zone.join("energy_uj"), "999000000").unwrap();
+
+        let mut meter = PackagePowerMeter::new(&zone);
+        let start = Instant::now();
+        assert_eq!(meter.read_package_power_at(start), None);
+
+        // 20 J over 2 s across a counter wrap
+        fs::write(zone.join("energy_uj"), "19000000").unwrap();
+        let watts = meter
+            .read_package_power_at(start + Duration::from_secs(2))
+            .unwrap();
+        assert!((watts - 10.0).abs() < 0.01);
+
+        fs::remove_dir_all(zone).unwrap();
+    }
+}
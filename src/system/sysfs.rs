@@ -0,0 +1,137 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[cfg(test)]
+use std::collections::BTreeMap;
+#[cfg(test)]
+use std::path::PathBuf;
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// Access to sysfs, so core-control logic can run against an in-memory fake in tests.
+pub trait SysfsProvider: Send + Sync {
+    fn read(&self, path: &Path) -> io::Result<String>;
+    fn write(&self, path: &Path, val: &str) -> io::Result<()>;
+    /// Names of the entries directly inside `dir`.
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>>;
+
+    /// Whether `path` exists as a file or a directory.
+    fn exists(&self, path: &Path) -> bool {
+        self.read(path).is_ok() || self.list(path).is_ok()
+    }
+}
+
+/// The real sysfs, through the filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LinuxSysfs;
+
+impl SysfsProvider for LinuxSysfs {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, val: &str) -> io::Result<()> {
+        fs::write(path, val)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        Ok(fs::read_dir(dir)?
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+}
+
+/// In-memory sysfs keyed by file path. Directories exist implicitly through the files
+/// below them, and writes only succeed on files that already exist, as in sysfs.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockSysfs {
+    files: Mutex<BTreeMap<PathBuf, String>>,
+}
+
+#[cfg(test)]
+impl MockSysfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, path: impl Into<PathBuf>, val: &str) {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.into(), val.to_string());
+    }
+
+    pub fn get(&self, path: impl AsRef<Path>) -> Option<String> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    /// Removes every file at or below `path`, like a CPU being hot-removed.
+    pub fn remove(&self, path: impl AsRef<Path>) {
+        self.files
+            .lock()
+            .unwrap()
+            .retain(|file, _| !file.starts_with(path.as_ref()));
+    }
+}
+
+#[cfg(test)]
+impl SysfsProvider for MockSysfs {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, path: &Path, val: &str) -> io::Result<()> {
+        match self.files.lock().unwrap().get_mut(path) {
+            Some(content) => {
+                *content = val.to_string();
+                Ok(())
+            }
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = self
+            .files
+            .lock()
+            .unwrap()
+            .keys()
+            .filter_map(|path| path.strip_prefix(dir).ok()?.components().next())
+            .map(|name| name.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        if names.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::NotFound));
+        }
+        names.dedup();
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_sysfs() {
+        let sysfs = MockSysfs::new();
+        sysfs.insert("/cpu/cpu1/online", "1");
+        sysfs.insert("/cpu/cpu1/topology/thread_siblings_list", "1");
+        sysfs.insert("/cpu/cpu2/online", "0");
+
+        assert_eq!(sysfs.list(Path::new("/cpu")).unwrap(), vec!["cpu1", "cpu2"]);
+        assert!(sysfs.exists(Path::new("/cpu/cpu1")));
+        assert!(!sysfs.exists(Path::new("/cpu/cpu3")));
+
+        sysfs.write(Path::new("/cpu/cpu2/online"), "1").unwrap();
+        assert_eq!(sysfs.read(Path::new("/cpu/cpu2/online")).unwrap(), "1");
+        assert!(sysfs.write(Path::new("/cpu/cpu3/online"), "1").is_err());
+    }
+}
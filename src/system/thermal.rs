@@ -0,0 +1,58 @@
+use log::debug;
+use std::fs;
+use std::path::Path;
+
+// Readings outside this range (in °C) are disconnected or broken sensors, not real heat
+const PLAUSIBLE_TEMP: std::ops::RangeInclusive<f32> = 1.0..=150.0;
+
+/// Hottest hwmon temperature in °C across all `temp*_input` files under `hwmon_path`
+/// (e.g. `/sys/class/hwmon`), or None when no sensor gives a plausible reading.
+pub fn get_max_temp(hwmon_path: &Path) -> Option<f32> {
+    let devices = match fs::read_dir(hwmon_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Could not read {}: {}", hwmon_path.display(), e);
+            return None;
+        }
+    };
+
+    devices
+        .flatten()
+        .filter_map(|device| fs::read_dir(device.path()).ok())
+        .flat_map(|files| files.flatten())
+        .filter(|file| {
+            let name = file.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("temp") && name.ends_with("_input")
+        })
+        .filter_map(|file| {
+            let millidegrees: i64 = fs::read_to_string(file.path()).ok()?.trim().parse().ok()?;
+            Some(millidegrees as f32 / 1000.0)
+        })
+        .filter(|celsius| PLAUSIBLE_TEMP.contains(celsius))
+        .max_by(|a, b| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_max_temp() {
+        let root = std::env::temp_dir().join(format!("observer-thermal-{}", std::process::id()));
+        fs::create_dir_all(root.join("hwmon0")).unwrap();
+        fs::create_dir_all(root.join("hwmon1")).unwrap();
+        fs::write(root.join("hwmon0/temp1_input"), "54000\n").unwrap();
+        fs::write(root.join("hwmon1/temp1_input"), "87500\n").unwrap();
+        fs::write(root.join("hwmon1/fan1_input"), "99000\n").unwrap();
+        // Unconnected and broken sensors
+        fs::write(root.join("hwmon1/temp2_input"), "0\n").unwrap();
+        fs::write(root.join("hwmon1/temp3_input"), "255000\n").unwrap();
+        fs::write(root.join("hwmon1/temp4_input"), "-273000\n").unwrap();
+
+        assert_eq!(get_max_temp(&root), Some(87.5));
+        assert_eq!(get_max_temp(&root.join("missing")), None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use log::{debug, warn};
+use std::process::Command;
+use std::thread;
+
+/// Runs a user hook through `sh -c` without blocking the caller. `env` is passed to the
+/// command (e.g. `OBSERVER_CORES`), and the child is reaped on a background thread.
+pub fn run_command(command: &str, env: &[(&str, String)]) {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+
+    match cmd.spawn() {
+        Ok(mut child) => {
+            debug!("Started hook '{}' (pid {})", command, child.id());
+            let command = command.to_string();
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => {
+                    warn!("Hook '{}' exited with {}", command, status)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to wait for hook '{}': {}", command, e),
+            });
+        }
+        Err(e) => warn!("Failed to run hook '{}': {}", command, e),
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Where a plain `http://` URL points. TLS isn't supported by this minimal client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+/// Parses an `http://host[:port][/path]` URL.
+pub fn parse_url(url: &str) -> Result<Endpoint, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("URL '{}' must start with http://", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| format!("Invalid port in URL '{}'", url))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("URL '{}' has no host", url));
+    }
+    Ok(Endpoint {
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+/// POSTs a JSON body and checks for a 2xx status.
+#[cfg(feature = "webhook")]
+pub fn post_json(endpoint: &Endpoint, body: &str, timeout: Duration) -> io::Result<()> {
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path,
+        endpoint.host,
+        body.len(),
+        body
+    );
+    request_body(endpoint, &request, timeout).map(|_| ())
+}
+
+/// GETs the endpoint and returns the response body of a 2xx reply.
+#[cfg(feature = "remote_target")]
+pub fn get(endpoint: &Endpoint, timeout: Duration) -> io::Result<String> {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        endpoint.path, endpoint.host
+    );
+    request_body(endpoint, &request, timeout)
+}
+
+fn request_body(endpoint: &Endpoint, request: &str, timeout: Duration) -> io::Result<String> {
+    let addr = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other("host did not resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+
+    // Connection: close, so the response ends when the server closes the stream
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let status = response.get(9..12).unwrap_or_default();
+    if !status.starts_with('2') {
+        return Err(io::Error::other(format!(
+            "endpoint returned HTTP {}",
+            status
+        )));
+    }
+    Ok(response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_string())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            parse_url("http://dash.local:8080/hooks/observer").unwrap(),
+            Endpoint {
+                host: "dash.local".to_string(),
+                port: 8080,
+                path: "/hooks/observer".to_string(),
+            }
+        );
+        assert_eq!(parse_url("http://10.0.0.2").unwrap().port, 80);
+        assert!(parse_url("https://dash.local").is_err());
+        assert!(parse_url("http://dash.local:http/").is_err());
+    }
+
+    /// Serves one canned response and returns the request it received.
+    fn serve_once(response: &'static str) -> (Endpoint, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .map_or(0, |l| l.parse().unwrap());
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let endpoint = parse_url(&format!("http://127.0.0.1:{}/events", port)).unwrap();
+        (endpoint, server)
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_post_json() {
+        let timeout = Duration::from_secs(5);
+        let (endpoint, server) = serve_once("HTTP/1.1 204 No Content\r\n\r\n");
+        post_json(&endpoint, r#"{"message":"4 cores"}"#, timeout).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /events HTTP/1.1\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"message\":\"4 cores\"}"));
+    }
+
+    #[cfg(feature = "remote_target")]
+    #[test]
+    fn test_get() {
+        let timeout = Duration::from_secs(5);
+        let (endpoint, server) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n6\n");
+        assert_eq!(get(&endpoint, timeout).unwrap(), "6\n");
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /events HTTP/1.1\r\n"));
+
+        let (endpoint, server) = serve_once("HTTP/1.1 503 Unavailable\r\n\r\n");
+        assert!(get(&endpoint, timeout).is_err());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use log::{Level, Log, Metadata, Record};
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "observer";
+
+static JOURNAL: OnceLock<UnixDatagram> = OnceLock::new();
+// Whether records go to the journal rather than stdout; log_backend can switch it later
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Sends records to journald over its native protocol while the journal is active, so
+/// `journalctl -o json` sees `PRIORITY` and any structured fields attached through
+/// `logging::log_event`. Otherwise records go to the stdout logger.
+struct JournalLogger {
+    stdout: env_logger::Logger,
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stdout.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stdout.matches(record) {
+            return;
+        }
+        if !send(record.level(), &record.args().to_string(), &[]) {
+            self.stdout.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.stdout.flush();
+    }
+}
+
+/// Installs the journal logger, logging to the journal from the start when `active` and
+/// the journal socket can be reached, and to `stdout` otherwise.
+pub fn init(stdout: env_logger::Logger, active: bool) -> Result<(), log::SetLoggerError> {
+    if let Ok(socket) = connect() {
+        let _ = JOURNAL.set(socket);
+    }
+    ACTIVE.store(active && JOURNAL.get().is_some(), Ordering::Relaxed);
+
+    log::set_max_level(stdout.filter());
+    log::set_boxed_logger(Box::new(JournalLogger { stdout }))
+}
+
+fn connect() -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(JOURNAL_SOCKET)?;
+    Ok(socket)
+}
+
+/// Switches logging between the journal and stdout. Fails, leaving stdout in place, when
+/// the journal socket couldn't be reached at startup.
+pub fn set_active(active: bool) -> io::Result<()> {
+    if active && JOURNAL.get().is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is unavailable", JOURNAL_SOCKET),
+        ));
+    }
+    ACTIVE.store(active, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Sends one journal entry. Returns false if the journal isn't active.
+pub fn send(level: Level, message: &str, fields: &[(&str, String)]) -> bool {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return false;
+    }
+    let Some(socket) = JOURNAL.get() else {
+        return false;
+    };
+
+    let mut payload = Vec::new();
+    encode_field(&mut payload, "MESSAGE", message);
+    encode_field(&mut payload, "PRIORITY", priority(level));
+    encode_field(&mut payload, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+    for (key, value) in fields {
+        encode_field(&mut payload, key, value);
+    }
+    // Nothing sensible to do if journald drops the datagram
+    let _ = socket.send(&payload);
+    true
+}
+
+/// Syslog priority for a log level, as journald expects in `PRIORITY=`.
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// Appends `KEY=value\n`, or the length-prefixed binary form when the value has newlines.
+fn encode_field(payload: &mut Vec<u8>, key: &str, value: &str) {
+    payload.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        payload.push(b'\n');
+        payload.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        payload.extend_from_slice(value.as_bytes());
+    } else {
+        payload.push(b'=');
+        payload.extend_from_slice(value.as_bytes());
+    }
+    payload.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_field() {
+        let mut payload = Vec::new();
+        encode_field(&mut payload, "CORE_COUNT", "6");
+        assert_eq!(payload, b"CORE_COUNT=6\n");
+
+        payload.clear();
+        encode_field(&mut payload, "MESSAGE", "a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(payload, expected);
+    }
+}
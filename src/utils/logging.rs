@@ -1,11 +1,138 @@
+use crate::config::LogBackend;
 use env_logger::{Builder, Target};
+use log::{Level, LevelFilter};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-pub fn init() {
-    let env = env_logger::Env::default().filter_or("RUST_LOG", "info");
+// Upper bound on remembered messages so a stream of unique errors can't grow the map forever
+const MAX_TRACKED_MESSAGES: usize = 64;
 
-    Builder::from_env(env)
+static DEDUP_INTERVAL_SEC: AtomicU64 = AtomicU64::new(300);
+static RECENT_MESSAGES: OnceLock<Mutex<HashMap<String, Instant>>> = OnceLock::new();
+
+fn env() -> env_logger::Env<'static> {
+    env_logger::Env::default().filter_or("RUST_LOG", "info")
+}
+
+/// RUST_LOG (default info), with `level` overriding it when given.
+fn builder(level: Option<LevelFilter>) -> Builder {
+    let mut builder = Builder::from_env(env());
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    builder
+}
+
+/// The plain-text stdout logger.
+fn stdout_builder(level: Option<LevelFilter>) -> Builder {
+    let mut builder = builder(level);
+    builder
         .target(Target::Stdout)
         .format_timestamp(None)
-        .format_module_path(false)
-        .init();
+        .format_module_path(false);
+    builder
+}
+
+pub fn init(level: Option<LevelFilter>) {
+    // systemd sets JOURNAL_STREAM for services, so log natively with structured fields.
+    // log_backend can still pick stdout or the journal once the config is read.
+    #[cfg(all(feature = "journald", unix))]
+    {
+        let under_systemd = std::env::var_os("JOURNAL_STREAM").is_some();
+        match super::journald::init(stdout_builder(level).build(), under_systemd) {
+            Ok(()) => return,
+            Err(e) => eprintln!("journald logger unavailable ({}), logging to stdout", e),
+        }
+    }
+
+    stdout_builder(level).init();
+}
+
+/// Applies the log_backend setting. `Auto` keeps what `init` chose.
+pub fn set_backend(backend: LogBackend) {
+    match backend {
+        LogBackend::Auto => {}
+        #[cfg(all(feature = "journald", unix))]
+        LogBackend::Stdout | LogBackend::Journald => {
+            if let Err(e) = super::journald::set_active(backend == LogBackend::Journald) {
+                log::warn!("log_backend = journald, but {}; logging to stdout", e);
+            }
+        }
+        #[cfg(not(all(feature = "journald", unix)))]
+        LogBackend::Stdout => {}
+        #[cfg(not(all(feature = "journald", unix)))]
+        LogBackend::Journald => log::warn!(
+            "log_backend = journald is set, but observer was built without the journald feature"
+        ),
+    }
+}
+
+/// Logs an event with structured fields such as `CORE_COUNT`. The fields reach journald
+/// when the `journald` feature is active; otherwise only the message is logged. With the
+/// `webhook` feature the event is also queued for webhook_url.
+pub fn log_event(level: Level, message: &str, fields: &[(&str, String)]) {
+    #[cfg(feature = "webhook")]
+    super::webhook::send(message, fields);
+    #[cfg(all(feature = "journald", unix))]
+    if log::log_enabled!(level) && super::journald::send(level, message, fields) {
+        return;
+    }
+    let _ = fields;
+    log::log!(level, "{}", message);
+}
+
+pub fn set_dedup_interval(seconds: u64) {
+    DEDUP_INTERVAL_SEC.store(seconds, Ordering::Relaxed);
+}
+
+/// Returns true if `message` hasn't been emitted within the dedup interval, and records it.
+fn should_emit(message: &str) -> bool {
+    let interval = Duration::from_secs(DEDUP_INTERVAL_SEC.load(Ordering::Relaxed));
+    let mut recent = RECENT_MESSAGES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let now = Instant::now();
+    recent.retain(|_, last| now.duration_since(*last) < interval);
+    if recent.contains_key(message) {
+        return false;
+    }
+    if recent.len() >= MAX_TRACKED_MESSAGES {
+        if let Some(oldest) = recent
+            .iter()
+            .min_by_key(|(_, last)| **last)
+            .map(|(m, _)| m.clone())
+        {
+            recent.remove(&oldest);
+        }
+    }
+    recent.insert(message.to_string(), now);
+    true
+}
+
+/// Logs `message` at most once per dedup interval, for errors that would otherwise repeat
+/// every iteration (e.g. sysfs permission denied when not running as root).
+pub fn log_deduplicated(level: Level, message: &str) {
+    if should_emit(message) {
+        log::log!(level, "{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_emit_deduplicates() {
+        set_dedup_interval(60);
+        assert!(should_emit("permission denied on cpu1"));
+        assert!(!should_emit("permission denied on cpu1"));
+        assert!(should_emit("permission denied on cpu2"));
+
+        set_dedup_interval(0);
+        assert!(should_emit("permission denied on cpu1"));
+    }
 }
@@ -0,0 +1,190 @@
+use crate::core::snapshot::CoreSnapshot;
+use log::{debug, info};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Scrape requests are a single GET; anything longer is not a Prometheus scraper
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// Values exported on `/metrics`, refreshed by the main loop each iteration.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub online_cores: usize,
+    pub avg_load_percent: f32,
+    pub on_battery: bool,
+    pub core_changes_total: u64,
+    pub battery_percent: Option<f32>, // Not exported while no battery is readable
+}
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+impl Metrics {
+    pub fn update(&mut self, snapshot: &CoreSnapshot, battery_percent: Option<f32>) {
+        self.online_cores = snapshot.current_cores;
+        self.avg_load_percent = snapshot.avg_load;
+        self.on_battery = snapshot.on_battery;
+        self.core_changes_total = snapshot.core_changes;
+        self.battery_percent = battery_percent;
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = write!(
+                out,
+                "# HELP {0} {1}\n# TYPE {0} {2}\n{0} {3}\n",
+                name, help, kind, value
+            );
+        };
+        metric(
+            "observer_online_cores",
+            "gauge",
+            "Logical CPUs observer keeps online.",
+            self.online_cores.to_string(),
+        );
+        metric(
+            "observer_avg_load_percent",
+            "gauge",
+            "Average CPU load across online cores.",
+            format!("{:.2}", self.avg_load_percent),
+        );
+        metric(
+            "observer_on_battery",
+            "gauge",
+            "1 when running on battery.",
+            u8::from(self.on_battery).to_string(),
+        );
+        metric(
+            "observer_core_changes_total",
+            "counter",
+            "Core count changes since startup.",
+            self.core_changes_total.to_string(),
+        );
+        if let Some(percent) = self.battery_percent {
+            metric(
+                "observer_battery_percent",
+                "gauge",
+                "Battery charge.",
+                format!("{:.1}", percent),
+            );
+        }
+        out
+    }
+}
+
+/// Binds `addr` and serves `GET /metrics` on a background thread, one scrape at a time.
+pub fn serve(addr: &str, metrics: SharedMetrics) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream, &metrics) {
+                    debug!("Metrics request failed: {}", e);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, metrics: &SharedMetrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 || request.len() > MAX_REQUEST_BYTES {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let target = request.lines().next().and_then(|line| {
+        let mut parts = line.split_whitespace();
+        (parts.next() == Some("GET"))
+            .then(|| parts.next())
+            .flatten()
+    });
+
+    let (status, body) = match target {
+        Some("/metrics") => {
+            let metrics = metrics.lock().unwrap_or_else(|e| e.into_inner());
+            ("200 OK", metrics.render())
+        }
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(addr: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        let snapshot = CoreSnapshot {
+            current_cores: 6,
+            avg_load: 42.5,
+            on_battery: true,
+            core_changes: 3,
+            ..CoreSnapshot::default()
+        };
+        metrics.update(&snapshot, None);
+        let text = metrics.render();
+        assert!(text.contains("# TYPE observer_core_changes_total counter\n"));
+        assert!(text.contains("\nobserver_online_cores 6\n"));
+        assert!(text.contains("\nobserver_avg_load_percent 42.50\n"));
+        assert!(text.contains("\nobserver_on_battery 1\n"));
+        assert!(!text.contains("observer_battery_percent"));
+
+        metrics.update(&snapshot, Some(81.0));
+        assert!(metrics
+            .render()
+            .ends_with("\nobserver_battery_percent 81.0\n"));
+    }
+
+    #[test]
+    fn test_serve() {
+        // Grab a free port, then hand it to serve
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let metrics = SharedMetrics::default();
+        metrics.lock().unwrap().online_cores = 4;
+        serve(&addr, metrics).unwrap();
+
+        let response = get(&addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nobserver_online_cores 4\n"));
+        assert!(get(&addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
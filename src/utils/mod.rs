@@ -1,4 +1,17 @@
+pub mod hooks;
+#[cfg(any(feature = "webhook", feature = "remote_target"))]
+pub mod http;
+#[cfg(all(feature = "journald", unix))]
+pub mod journald;
 pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod signals;
+#[cfg(unix)]
+pub mod status_socket;
+pub mod systemd;
+#[cfg(feature = "webhook")]
+pub mod webhook;
 
 // Remove unused re-export
 // pub use logging::init as init_logging;
@@ -0,0 +1,94 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static DURATION_RESET: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn toggle_pause(_signal: libc::c_int) {
+    // Only an atomic flip here; anything else isn't async-signal-safe
+    PAUSED.fetch_xor(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn request_duration_reset(_signal: libc::c_int) {
+    DURATION_RESET.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn request_reload(_signal: libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn install_handler(signal: libc::c_int, handler: extern "C" fn(libc::c_int)) -> io::Result<()> {
+    // SAFETY: the handlers only touch atomics, and the sigaction struct is fully
+    // initialised before it is passed to the kernel.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Installs a SIGUSR2 handler that toggles the paused flag read by `is_paused`.
+#[cfg(unix)]
+pub fn install_pause_toggle() -> io::Result<()> {
+    install_handler(libc::SIGUSR2, toggle_pause)
+}
+
+/// Installs a SIGUSR1 handler that requests a reset of the power-state durations.
+#[cfg(unix)]
+pub fn install_duration_reset() -> io::Result<()> {
+    install_handler(libc::SIGUSR1, request_duration_reset)
+}
+
+/// Installs a SIGHUP handler that requests a configuration reload.
+#[cfg(unix)]
+pub fn install_reload() -> io::Result<()> {
+    install_handler(libc::SIGHUP, request_reload)
+}
+
+#[cfg(not(unix))]
+pub fn install_pause_toggle() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SIGUSR2 is only available on Unix",
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn install_duration_reset() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SIGUSR1 is only available on Unix",
+    ))
+}
+
+#[cfg(not(unix))]
+pub fn install_reload() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SIGHUP is only available on Unix",
+    ))
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Returns whether a duration reset was requested since the last call.
+pub fn take_duration_reset() -> bool {
+    DURATION_RESET.swap(false, Ordering::SeqCst)
+}
+
+/// Returns whether a config reload was requested since the last call.
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
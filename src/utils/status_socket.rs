@@ -0,0 +1,103 @@
+use crate::core::snapshot::CoreSnapshot;
+use log::{debug, info, warn};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The snapshot the main loop publishes each iteration and the socket thread serves.
+pub type SharedSnapshot = Arc<Mutex<CoreSnapshot>>;
+
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A Unix socket answering `status` with the latest snapshot as one line of JSON. Clients
+/// are served one after another on a background thread; the socket file is removed on drop.
+pub struct StatusSocket {
+    path: PathBuf,
+}
+
+impl StatusSocket {
+    /// Binds `path`, replacing a socket left behind by an earlier run, and starts serving.
+    pub fn bind(path: &Path, snapshot: SharedSnapshot) -> io::Result<Self> {
+        if fs::symlink_metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        info!("Serving status queries on {}", path.display());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve_client(stream, &snapshot) {
+                            debug!("Status client disconnected: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("Failed to accept status client: {}", e),
+                }
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for StatusSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answers each newline-terminated command until the client closes the connection.
+fn serve_client(stream: UnixStream, snapshot: &SharedSnapshot) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let reply = match line?.trim() {
+            "status" => {
+                let snapshot = snapshot.lock().unwrap_or_else(|e| e.into_inner());
+                serde_json::to_string(&*snapshot)?
+            }
+            other => {
+                serde_json::json!({ "error": format!("unknown command '{}'", other) }).to_string()
+            }
+        };
+        writer.write_all(reply.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(path: &Path, command: &str) -> serde_json::Value {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(command.as_bytes()).unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[test]
+    fn test_status_socket() {
+        let path = std::env::temp_dir().join(format!("observer-sock-{}", std::process::id()));
+        let snapshot = SharedSnapshot::default();
+        let socket = StatusSocket::bind(&path, snapshot.clone()).unwrap();
+
+        snapshot.lock().unwrap().current_cores = 6;
+        assert_eq!(query(&path, "status\n")["current_cores"], 6);
+        snapshot.lock().unwrap().current_cores = 4;
+        assert_eq!(query(&path, "status\n")["current_cores"], 4);
+        assert!(query(&path, "reload\n")["error"].is_string());
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
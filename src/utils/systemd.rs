@@ -0,0 +1,77 @@
+use std::env;
+use std::ffi::OsStr;
+use std::io;
+
+/// Sends a notification such as `READY=1` to the service manager through
+/// `$NOTIFY_SOCKET`. Returns false when observer wasn't started with a notify socket
+/// (not under systemd, or not `Type=notify`).
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_to(&socket, state).map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// Whether systemd expects `WATCHDOG=1` pings: `$WATCHDOG_USEC` is set and, if
+/// `$WATCHDOG_PID` is given, it names this process.
+pub fn watchdog_enabled() -> bool {
+    let pid_matches =
+        env::var("WATCHDOG_PID").map_or(true, |pid| pid.parse() == Ok(std::process::id()));
+    env::var("WATCHDOG_USEC").is_ok_and(|usec| usec.parse::<u64>().is_ok_and(|usec| usec > 0))
+        && pid_matches
+}
+
+#[cfg(unix)]
+fn notify_to(socket: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    // A leading '@' names a socket in the abstract namespace
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+            let addr = SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract notify sockets are Linux-only",
+            ))
+        }
+        None => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_to(_socket: &OsStr, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_notify_to() {
+        let path = std::env::temp_dir().join(format!("observer-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_to(path.as_os_str(), "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        std::fs::remove_file(path).unwrap();
+        assert!(notify_to(OsStr::new("/nonexistent/notify"), "READY=1").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use super::http;
+use log::Level;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+// Events waiting for delivery; anything beyond this is dropped rather than blocking
+const QUEUE_CAPACITY: usize = 32;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+static QUEUE: OnceLock<SyncSender<String>> = OnceLock::new();
+
+/// Starts the delivery thread. Events are posted one at a time, in order, as JSON.
+pub fn init(url: &str) -> Result<(), String> {
+    let endpoint = http::parse_url(url)?;
+    let (sender, receiver) = mpsc::sync_channel::<String>(QUEUE_CAPACITY);
+    QUEUE
+        .set(sender)
+        .map_err(|_| "webhook already initialised".to_string())?;
+
+    thread::Builder::new()
+        .name("webhook".to_string())
+        .spawn(move || {
+            for body in receiver {
+                if let Err(e) = http::post_json(&endpoint, &body, TIMEOUT) {
+                    super::logging::log_deduplicated(
+                        Level::Warn,
+                        &format!("Webhook delivery to {} failed: {}", endpoint.host, e),
+                    );
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to start webhook thread: {}", e))?;
+    Ok(())
+}
+
+/// Queues an event for the webhook without blocking; a full queue drops the event.
+pub fn send(message: &str, fields: &[(&str, String)]) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    let fields: serde_json::Map<String, serde_json::Value> = fields
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.clone().into()))
+        .collect();
+    let body = serde_json::json!({ "message": message, "fields": fields }).to_string();
+    match queue.try_send(body) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => super::logging::log_deduplicated(
+            Level::Warn,
+            "Webhook queue full, dropping events until the endpoint catches up",
+        ),
+        Err(TrySendError::Disconnected(_)) => {}
+    }
+}